@@ -1,10 +1,39 @@
 use std::fs::write;
-use resvg::tiny_skia::*;
-use resvg::usvg::TreeParsing;
-use resvg::*;
 use fractal_tribute_integrity::board::*;
+use fractal_tribute_integrity::game_move::*;
+use fractal_tribute::board_animation::*;
+use fractal_tribute::board_images::*;
+use fractal_tribute::mask_assets::*;
+
+const USAGE: &str = "usage:
+  cli_tools [masks]
+      regenerate the pattern mask assets the coordinator includes
+  cli_tools timelapse <moves file> <output file> [--format Gif|Apng] [--stride N] [--delay MS]
+      [--size Small|Large|PIXELS] [--from N] [--to N] [--width N] [--height N]
+      render moves exported with export_game_moves as an animation
+  cli_tools svg|svg-with-defs|png|board-bytes <moves file> <output file> [--moves N]
+      [--width N] [--height N] [--size Small|Large|PIXELS]
+      render the board after the first N exported moves (all of them by default) without a conductor.
+      svg leaves out the pattern defs the ui already has, --size is only used by png";
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        None | Some("masks") => {
+            save_pattern_masks();
+            Ok(())
+        }
+        Some("timelapse") => timelapse(&args[1..]),
+        Some(output @ ("svg" | "svg-with-defs" | "png" | "board-bytes")) => render(output, &args[1..]),
+        Some(_) => Err(String::from(USAGE)),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn save_pattern_masks() {
     // loop for all graphic options
     for option in 0..GRAPHIC_OPTIONS {
         println!("saving pattern mask for option {}", option + 1);
@@ -12,55 +41,107 @@ fn main() {
     }
 }
 
-fn save_pattern_mask(option: u8) {
-    let svg_document = Board::generate_pattern_mask(option);
-    // render the large option
-    let pixmap = svg_to_png(SvgToPngArgs {
-        svg_data: svg_document.clone(),
-        scale: 0.5,
-    }).unwrap();
-    // write the png bytes to a new file
-    // pixmap.save_png(format!("./pattern-masks/large/{}.png", option + 1)).unwrap();
-    write_image_buf(pixmap, format!("./pattern-masks/large/{}.cache", option + 1));
+// splits arguments into positional ones and --name value options
+fn parse_args(args: &[String]) -> Result<(Vec<String>, std::collections::HashMap<String, String>), String> {
+    let mut positional = Vec::new();
+    let mut options = std::collections::HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix("--") {
+            Some(name) => {
+                let value = args.next().ok_or(format!("missing value for --{}\n{}", name, USAGE))?;
+                options.insert(name.to_string(), value.clone());
+            }
+            None => positional.push(arg.clone()),
+        }
+    }
+    Ok((positional, options))
+}
 
-    // render the small option
-    let pixmap = svg_to_png(SvgToPngArgs {
-        svg_data: svg_document.clone(),
-        scale: 0.15,
-    }).unwrap();
-    // write the png bytes to a new file
-    // pixmap.save_png(format!("./pattern-masks/small/{}.png", option + 1)).unwrap();
-    write_image_buf(pixmap, format!("./pattern-masks/small/{}.cache", option + 1));
+fn option_or<T: std::str::FromStr>(options: &std::collections::HashMap<String, String>, name: &str, default: T) -> Result<T, String> {
+    match options.get(name) {
+        Some(value) => value.parse().map_err(|_| format!("invalid value for --{}: {}", name, value)),
+        None => Ok(default),
+    }
 }
 
-fn write_image_buf(pixmap: Pixmap, path: String) {
-    let png_bytes = pixmap.encode_png().unwrap();
-    let image_buf = image::load_from_memory(&png_bytes).unwrap();
-    let raw = image_buf.into_rgba8().into_raw();
-    let _res = write(path, raw);
+// unlike the zome, which keeps to MAX_BOARD_PIXELS, any size can be rendered here
+fn parse_board_size(options: &std::collections::HashMap<String, String>) -> Result<BoardSize, String> {
+    BoardSize::parse_up_to(&option_or(options, "size", String::from("Large"))?, u32::MAX)
+        .ok_or(String::from("--size must be Small, Large or a number of pixels"))
 }
-pub struct SvgToPngArgs {
-    pub svg_data: String,
-    pub scale: f32,
+
+fn timelapse(args: &[String]) -> Result<(), String> {
+    let (positional, options) = parse_args(args)?;
+    let (moves_path, output_path) = match positional.as_slice() {
+        [moves_path, output_path] => (moves_path, output_path),
+        _ => return Err(String::from(USAGE)),
+    };
+
+    let default_format = if output_path.ends_with(".png") || output_path.ends_with(".apng") { "Apng" } else { "Gif" };
+    let format: AnimationFormat = option_or(&options, "format", default_format.to_string())?
+        .parse()
+        .map_err(|_| String::from("--format must be Gif or Apng"))?;
+    let board_size = parse_board_size(&options)?;
+    let stride = option_or(&options, "stride", 1usize)?;
+    let frame_delay_ms = option_or(&options, "delay", 100u32)?;
+    let width = option_or(&options, "width", BOARD_SIZE)?;
+    let height = option_or(&options, "height", BOARD_SIZE)?;
+
+    let game_moves = read_game_moves(moves_path)?;
+    let from_move = option_or(&options, "from", 0usize)?;
+    let to_move = option_or(&options, "to", game_moves.len())?;
+    if from_move > to_move || to_move > game_moves.len() {
+        return Err(format!("--from and --to must be within 0 to {} moves", game_moves.len()));
+    }
+
+    let start_board = Board::reconstruct_from_game_moves(width, height, &game_moves[..from_move]);
+    let mask_images = get_mask_images(&board_size)?;
+    let tile_size = tile_size_for(board_size, &start_board)?;
+    let boards = timelapse_boards(start_board, &game_moves[from_move..to_move], stride);
+    println!("rendering {} frames", boards.len());
+    let animation = encode_animation(&boards, &mask_images, tile_size, frame_delay_ms, format)?;
+    write(output_path, animation).map_err(|e| format!("could not write {}: {}", output_path, e))
 }
 
-pub fn svg_to_png(args: SvgToPngArgs) -> Result<Pixmap, Box<dyn std::error::Error>> {
-    // Parse the SVG string
-    let opts = resvg::usvg::Options::default();
-    let utree = usvg::Tree::from_str(&args.svg_data, &opts)
-        .map_err(|e| format!("Could not parse SVG: {:?} {}", e, args.svg_data))?;
-    
-    let rtree = resvg::Tree::from_usvg(&utree);
+fn read_game_moves(moves_path: &str) -> Result<Vec<GameMove>, String> {
+    let bytes = std::fs::read(moves_path).map_err(|e| format!("could not read {}: {}", moves_path, e))?;
+    game_moves_from_bytes(&bytes).map_err(|e| format!("could not decode {}: {}", moves_path, e))
+}
 
-    // Create a pixmap for rendering
-    let mut binding = Pixmap::new((rtree.size.width() * args.scale) as u32, (rtree.size.height() * args.scale) as u32)
-        .ok_or("Could not create Pixmap")?;
-    let mut pixmap = binding.as_mut();
-    
-    rtree.render(Transform::from_scale(args.scale, args.scale), &mut pixmap);
+// one board from an exported game, written in the form output names
+fn render(output: &str, args: &[String]) -> Result<(), String> {
+    let (positional, options) = parse_args(args)?;
+    let (moves_path, output_path) = match positional.as_slice() {
+        [moves_path, output_path] => (moves_path, output_path),
+        _ => return Err(String::from(USAGE)),
+    };
+    let width = option_or(&options, "width", BOARD_SIZE)?;
+    let height = option_or(&options, "height", BOARD_SIZE)?;
 
-    let pixmap = pixmap.to_owned();
+    let game_moves = read_game_moves(moves_path)?;
+    let move_count = option_or(&options, "moves", game_moves.len())?;
+    if move_count > game_moves.len() {
+        return Err(format!("--moves must be at most {}", game_moves.len()));
+    }
+    let board = Board::reconstruct_from_game_moves(width, height, &game_moves[..move_count]);
 
-    Ok(pixmap)
+    let bytes = match output {
+        "svg" => board.generate_svg().into_bytes(),
+        "svg-with-defs" => board.generate_svg_with_defs().into_bytes(),
+        "png" => {
+            let board_size = parse_board_size(&options)?;
+            board_image_bytes(board, board_size, ImageFormat::Png).map_err(|e| e.to_string())?
+        }
+        _ => board.to_bytes(),
+    };
+    write(output_path, bytes).map_err(|e| format!("could not write {}: {}", output_path, e))
 }
 
+fn save_pattern_mask(option: u8) {
+    for (board_size, directory) in [(BoardSize::Large, "large"), (BoardSize::Small, "small")] {
+        let mask = rasterise_pattern_mask(option, board_size.pixels()).unwrap();
+        let path = format!("./pattern-masks/{}/{}.mask", directory, option + 1);
+        write(&path, encode_mask(&mask, MASK_BLOCK_SIZE)).unwrap();
+    }
+}
@@ -0,0 +1,67 @@
+// checks each image format the coordinator serves is the same picture, and that they share the
+// render cache
+use fractal_tribute::board_images::*;
+use fractal_tribute::render_cache::*;
+use fractal_tribute_integrity::board::*;
+use fractal_tribute_integrity::game_move::*;
+use std::io::Cursor;
+
+fn painted_board() -> Board {
+    let mut board = Board::default();
+    let changes = (0..MAX_PIXEL_CHANGES)
+        .map(|i| PixelChange {
+            x: i,
+            y: i * 3 % BOARD_SIZE,
+            color: Color { r: (i * 20) as u8, g: 90, b: 200 },
+            graphic_option: (i % (GRAPHIC_OPTIONS * 2 + 2)) as u8,
+        })
+        .collect();
+    board.apply_game_move(&GameMove { changes });
+    board
+}
+
+fn decode_webp(bytes: &[u8]) -> Vec<u8> {
+    let mut decoder = image_webp::WebPDecoder::new(Cursor::new(bytes)).unwrap();
+    let mut pixels = vec![0; decoder.output_buffer_size().unwrap()];
+    decoder.read_image(&mut pixels).unwrap();
+    if decoder.has_alpha() {
+        pixels
+    } else {
+        pixels.chunks(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect()
+    }
+}
+
+#[test]
+fn png_and_webp_are_the_same_picture() {
+    let board = painted_board();
+    let png = board_image(board.clone(), BoardSize::Small, ImageFormat::Png).unwrap();
+    let webp = board_image(board.clone(), BoardSize::Small, ImageFormat::Webp).unwrap();
+    assert_eq!(png.content_type, "image/png");
+    assert_eq!(webp.content_type, "image/webp");
+
+    let png_pixels = image::load_from_memory(&png.bytes).unwrap().into_rgba8();
+    assert_eq!(png_pixels.dimensions(), (600, 600));
+    assert_eq!(decode_webp(&webp.bytes), png_pixels.into_raw());
+
+    let svg = board_image(board.clone(), BoardSize::Small, ImageFormat::Svg).unwrap();
+    assert_eq!(svg.content_type, "image/svg+xml");
+    assert_eq!(String::from_utf8(svg.bytes).unwrap(), board.generate_svg_with_defs());
+}
+
+#[test]
+fn formats_share_the_render_cache() {
+    let mut board = painted_board();
+    board.apply_game_move(&GameMove { changes: vec![PixelChange::erase(0, 0)] });
+
+    let before = RENDER_CACHE.lock().unwrap().stats();
+    let first = board_image_bytes(board.clone(), BoardSize::Small, ImageFormat::Png).unwrap();
+    let second = board_image_bytes(board.clone(), "600".parse().unwrap(), ImageFormat::Png).unwrap();
+    // svgs don't depend on the size they were asked for
+    board_image_bytes(board.clone(), BoardSize::Small, ImageFormat::Svg).unwrap();
+    board_image_bytes(board, BoardSize::Large, ImageFormat::Svg).unwrap();
+    let after = RENDER_CACHE.lock().unwrap().stats();
+
+    assert_eq!(first, second);
+    assert!(after.hits >= before.hits + 2);
+    assert!(after.bytes as usize >= first.len());
+}
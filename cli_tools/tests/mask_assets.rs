@@ -0,0 +1,57 @@
+// checks the mask asset format round trips, that the baked assets are up to date with the pattern
+// mask svgs, and that masks are only decoded when a tile needs them
+use fractal_tribute::board_images::*;
+use fractal_tribute::mask_assets::*;
+use fractal_tribute_integrity::board::*;
+use fractal_tribute_integrity::game_move::*;
+use image::Luma;
+
+#[test]
+fn round_trips_masks_that_do_not_fill_their_last_blocks() {
+    // stripes repeat every 4 pixels, so most blocks are the same
+    let mask = Mask::from_fn(50, 37, |x, y| Luma([if (x + y) % 4 == 0 { 255 } else { (x * y % 7) as u8 }]));
+    for block_size in [1, 4, MASK_BLOCK_SIZE, 64] {
+        assert_eq!(decode_mask(&encode_mask(&mask, block_size)).unwrap(), mask);
+    }
+}
+
+#[test]
+fn rejects_damaged_assets() {
+    let bytes = encode_mask(&Mask::from_fn(30, 30, |x, _| Luma([x as u8])), MASK_BLOCK_SIZE);
+    assert!(decode_mask(&bytes[..bytes.len() - 3]).is_err());
+    assert!(decode_mask(&bytes[1..]).is_err());
+    let mut wrong_size = bytes.clone();
+    wrong_size[4] += MASK_BLOCK_SIZE as u8;
+    assert!(decode_mask(&wrong_size).is_err());
+}
+
+#[test]
+fn baked_small_masks_match_the_svgs() {
+    let mask_images = get_mask_images(&BoardSize::Small).unwrap();
+    for option in 0..GRAPHIC_OPTIONS {
+        let rasterised = rasterise_pattern_mask(option as u8, BoardSize::Small.pixels()).unwrap();
+        assert!(
+            *mask_images.get(option) == rasterised,
+            "the baked mask for option {} is out of date, run cli_tools masks",
+            option + 1
+        );
+    }
+}
+
+#[test]
+fn only_decodes_the_masks_a_board_uses() {
+    // a size of its own so no other test has loaded any of its masks
+    let mask_images = get_mask_images(&BoardSize::Pixels(120)).unwrap();
+    let mut board = Board::default();
+    let color = Color { r: 10, g: 20, b: 30 };
+    board.apply_game_move(&GameMove {
+        changes: vec![
+            PixelChange { x: 0, y: 0, color, graphic_option: 3 },
+            PixelChange { x: 1, y: 0, color, graphic_option: (GRAPHIC_OPTIONS + 5) as u8 },
+            PixelChange { x: 2, y: 0, color, graphic_option: (GRAPHIC_OPTIONS * 2) as u8 },
+        ],
+    });
+    draw_board(board.clone(), &mask_images, tile_size_for(BoardSize::Pixels(120), &board).unwrap());
+    let loaded: Vec<usize> = (0..GRAPHIC_OPTIONS).filter(|option| mask_images.is_loaded(*option)).collect();
+    assert_eq!(loaded, vec![3, 5]);
+}
@@ -0,0 +1,79 @@
+// runs the offline render subcommands on an exported game and checks they draw the same board
+// the zome would
+use fractal_tribute_integrity::board::*;
+use fractal_tribute_integrity::game_move::*;
+use fractal_tribute::board_images::MAX_BOARD_PIXELS;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn game_moves() -> Vec<GameMove> {
+    (0..5)
+        .map(|i| GameMove {
+            changes: vec![
+                PixelChange { x: i, y: i * 7 % BOARD_SIZE, color: Color { r: 200, g: (i * 40) as u8, b: 10 }, graphic_option: i as u8 },
+                PixelChange { x: i + 10, y: 3, color: Color { r: 5, g: 90, b: 250 }, graphic_option: (GRAPHIC_OPTIONS * 2) as u8 },
+            ],
+        })
+        .collect()
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("cli_tools_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn render(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_cli_tools")).args(args).output().unwrap()
+}
+
+#[test]
+fn renders_exported_moves_without_a_conductor() {
+    let directory = scratch_dir("render");
+    let moves_path = directory.join("moves.bin");
+    std::fs::write(&moves_path, game_moves_to_bytes(&game_moves())).unwrap();
+    let moves_path = moves_path.to_str().unwrap();
+    let board = Board::reconstruct_from_game_moves(BOARD_SIZE, BOARD_SIZE, &game_moves()[..3]);
+
+    let expected = [
+        ("svg", board.generate_svg().into_bytes()),
+        ("svg-with-defs", board.generate_svg_with_defs().into_bytes()),
+        ("board-bytes", board.to_bytes()),
+    ];
+    for (subcommand, expected) in expected {
+        let output_path = directory.join(subcommand);
+        let output = render(&[subcommand, moves_path, output_path.to_str().unwrap(), "--moves", "3"]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(std::fs::read(&output_path).unwrap(), expected, "{}", subcommand);
+    }
+
+    let png_path = directory.join("board.png");
+    let output = render(&["png", moves_path, png_path.to_str().unwrap(), "--size", "400"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let png = image::open(&png_path).unwrap().to_rgba8();
+    assert_eq!((png.width(), png.height()), (400, 400));
+
+    // bigger than the zome will draw
+    let size = (MAX_BOARD_PIXELS / BOARD_SIZE as u32 + 1) * BOARD_SIZE as u32;
+    let output = render(&["png", moves_path, png_path.to_str().unwrap(), "--size", &size.to_string()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let png = image::open(&png_path).unwrap().to_rgba8();
+    assert_eq!((png.width(), png.height()), (size, size));
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn rejects_more_moves_than_were_exported() {
+    let directory = scratch_dir("too_many");
+    let moves_path = directory.join("moves.bin");
+    std::fs::write(&moves_path, game_moves_to_bytes(&game_moves())).unwrap();
+    let output_path = directory.join("board.svg");
+
+    let output = render(&["svg", moves_path.to_str().unwrap(), output_path.to_str().unwrap(), "--moves", "6"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--moves must be at most 5"));
+    assert!(!output_path.exists());
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
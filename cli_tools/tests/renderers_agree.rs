@@ -0,0 +1,119 @@
+// renders boards through Board::generate_svg_with_defs and resvg, and through the png renderer the
+// coordinator uses, and checks they produce the same picture
+use fractal_tribute::board_images::*;
+use fractal_tribute_integrity::board::*;
+use fractal_tribute_integrity::game_move::*;
+use image::{ImageBuffer, Rgba};
+use resvg::tiny_skia::{Color as SkiaColor, Pixmap, Transform};
+use resvg::usvg::TreeParsing;
+
+// both renderers anti-alias the pattern edges separately, so allow for rounding there, but no
+// tile may look different
+const MAX_CHANNEL_DIFFERENCE: u8 = 8;
+const MAX_DIFFERING_PIXELS_PER_TILE: f64 = 0.1;
+
+fn render_svg(board: &Board, pixels: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let svg_data = board.generate_svg_with_defs();
+    let utree = resvg::usvg::Tree::from_str(&svg_data, &resvg::usvg::Options::default()).unwrap();
+    let rtree = resvg::Tree::from_usvg(&utree);
+    let scale = pixels as f32 / rtree.size.width().max(rtree.size.height());
+
+    let mut pixmap = Pixmap::new(
+        (rtree.size.width() * scale).round() as u32,
+        (rtree.size.height() * scale).round() as u32,
+    )
+    .unwrap();
+    // the png renderer draws on white, empty tiles in the svg are transparent
+    pixmap.fill(SkiaColor::WHITE);
+    rtree.render(Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    let raw = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    ImageBuffer::from_raw(pixmap.width(), pixmap.height(), raw).unwrap()
+}
+
+fn render_png(board: &Board) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let mask_images = get_mask_images(&BoardSize::Small).unwrap();
+    let tile_size = tile_size_for(BoardSize::Small, board).unwrap();
+    draw_board(board.clone(), &mask_images, tile_size)
+}
+
+fn assert_same_picture(board: &Board) {
+    let png = render_png(board);
+    let svg = render_svg(board, BoardSize::Small.pixels());
+    assert_eq!(png.dimensions(), svg.dimensions());
+
+    let (across, down) = board.canvas_tiles();
+    let tile_size = png.width() / across as u32;
+    let mut differing_pixels = vec![0u32; across * down];
+    for (x, y, png_pixel) in png.enumerate_pixels() {
+        let svg_pixel = svg.get_pixel(x, y);
+        let difference = (0..4).map(|i| png_pixel[i].abs_diff(svg_pixel[i])).max().unwrap();
+        if difference > MAX_CHANNEL_DIFFERENCE {
+            differing_pixels[(y / tile_size) as usize * across + (x / tile_size) as usize] += 1;
+        }
+    }
+    for (index, differing) in differing_pixels.into_iter().enumerate() {
+        assert!(
+            differing as f64 / (tile_size * tile_size) as f64 <= MAX_DIFFERING_PIXELS_PER_TILE,
+            "{} pixels of the tile {} across and {} down differ between the svg and png renderers",
+            differing,
+            index % across,
+            index / across
+        );
+    }
+}
+
+fn change(x: usize, y: usize, graphic_option: u8) -> PixelChange {
+    let color = Color { r: (x * 6) as u8, g: 40 + (y * 5) as u8, b: 200 - graphic_option * 5 };
+    PixelChange { x, y, color, graphic_option }
+}
+
+#[test]
+fn every_graphic_option_matches() {
+    let mut board = Board::default();
+    let changes = (0..BOARD_SIZE)
+        .flat_map(|y| (0..BOARD_SIZE).map(move |x| (x, y)))
+        .map(|(x, y)| change(x, y, ((x + y * 3) % (GRAPHIC_OPTIONS * 2 + 2)) as u8))
+        .collect();
+    board.apply_game_move(&GameMove { changes });
+    assert_same_picture(&board);
+}
+
+#[test]
+fn orientation_matches_on_a_non_square_board() {
+    let mut board = Board::new(BOARD_SIZE, 25);
+    let changes = vec![
+        change(0, 0, (GRAPHIC_OPTIONS * 2) as u8),
+        change(BOARD_SIZE - 1, 0, 3),
+        change(0, 24, (GRAPHIC_OPTIONS + 5) as u8),
+        change(7, 19, 11),
+    ];
+    board.apply_game_move(&GameMove { changes });
+    assert_same_picture(&board);
+
+    // a change's y runs across the canvas, see Board::canvas_position
+    let png = render_png(&board);
+    let tile_size = tile_size_for(BoardSize::Small, &board).unwrap();
+    assert_eq!(png.dimensions(), (25 * tile_size, BOARD_SIZE as u32 * tile_size));
+    assert_eq!(*png.get_pixel(tile_size / 2, tile_size / 2), Rgba([0, 40, 30, 255]));
+}
+
+#[test]
+fn erased_tiles_match_empty_ones() {
+    let mut board = Board::default();
+    board.apply_game_move(&GameMove { changes: (0..MAX_PIXEL_CHANGES).map(|x| change(x, 2, 4)).collect() });
+    board.apply_game_move(&GameMove { changes: (0..10).map(|x| PixelChange::erase(x, 2)).collect() });
+    assert_same_picture(&board);
+    assert_eq!(render_png(&board), render_png(&Board::reconstruct_from_game_moves(
+        BOARD_SIZE,
+        BOARD_SIZE,
+        &[GameMove { changes: (10..MAX_PIXEL_CHANGES).map(|x| change(x, 2, 4)).collect() }],
+    )));
+}
@@ -0,0 +1,76 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use fractal_tribute::board_images::*;
+use fractal_tribute_integrity::*;
+
+// every tile painted, cycling through the colours and graphic options
+fn painted_board() -> Board {
+    let mut board = Board::default();
+    let changes = (0..board.height)
+        .flat_map(|y| (0..board.width).map(move |x| (x, y)))
+        .map(|(x, y)| PixelChange {
+            x,
+            y,
+            color: Color { r: (x * 6) as u8, g: (y * 6) as u8, b: 128 },
+            graphic_option: ((x + y) % (GRAPHIC_OPTIONS * 2 + 1)) as u8,
+        })
+        .collect();
+    board.apply_game_move(&GameMove { changes });
+    board
+}
+
+// a full move of MAX_PIXEL_CHANGES changes scattered over the board. every seed touches the same
+// tiles with different values, so alternating seeds redraws all of them each time
+fn scattered_move(seed: usize) -> GameMove {
+    let changes = (0..MAX_PIXEL_CHANGES)
+        .map(|i| PixelChange {
+            x: (i * 7) % BOARD_SIZE,
+            y: (i * 13) % BOARD_SIZE,
+            color: Color { r: 200, g: (seed * 40) as u8, b: 10 },
+            graphic_option: ((i + seed) % (GRAPHIC_OPTIONS * 2)) as u8,
+        })
+        .collect();
+    GameMove { changes }
+}
+
+fn render_after_move(c: &mut Criterion) {
+    let board = painted_board();
+    let moves = [scattered_move(1), scattered_move(2)];
+
+    for board_size in [BoardSize::Small, BoardSize::Large] {
+        let mask_images = get_mask_images(&board_size).unwrap();
+        let mask_images = &*mask_images;
+        let tile_size = tile_size_for(board_size, &board).unwrap();
+
+        // both renderers must produce the same image for the comparison to mean anything
+        let mut renderer = IncrementalRenderer::new(board.clone(), mask_images, tile_size);
+        renderer.apply_game_move(&moves[0], mask_images);
+        assert!(renderer.canvas() == &draw_board(renderer.board().clone(), mask_images, tile_size));
+
+        let mut group = c.benchmark_group(format!("{:?}", board_size));
+        group.sample_size(10);
+
+        group.bench_function("full redraw", |b| {
+            let mut board = board.clone();
+            let mut i = 0;
+            b.iter(|| {
+                board.apply_game_move(&moves[i % 2]);
+                i += 1;
+                draw_board(board.clone(), mask_images, tile_size)
+            })
+        });
+
+        group.bench_function("incremental", |b| {
+            let mut renderer = IncrementalRenderer::new(board.clone(), mask_images, tile_size);
+            let mut i = 0;
+            b.iter(|| {
+                renderer.apply_game_move(&moves[i % 2], mask_images);
+                i += 1;
+            })
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, render_after_move);
+criterion_main!(benches);
@@ -1,14 +1,141 @@
 use hdk::prelude::*;
 use fractal_tribute_integrity::*;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// the time buckets that game moves have been linked under, oldest first
+fn get_game_move_buckets() -> ExternResult<Vec<i64>> {
+    let root = all_game_moves_path().typed(LinkTypes::GameMoveBuckets)?;
+    let mut buckets: Vec<i64> = root
+        .children_paths()?
+        .into_iter()
+        .filter_map(|path| {
+            let component = path.leaf()?;
+            String::try_from(component).ok()?.parse().ok()
+        })
+        .collect();
+    // concurrent moves can each create the link to a new bucket
+    buckets.sort_unstable();
+    buckets.dedup();
+    Ok(buckets)
+}
+
+// the action timestamps of the game moves links point at, which never change once fetched
+static MOVE_TIMESTAMPS: Lazy<Mutex<HashMap<ActionHash, Timestamp>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// an AllGameMoves link and the time its game move was made
+#[derive(Clone, Debug)]
+pub struct GameMoveLink {
+    pub link: Link,
+    pub timestamp: Timestamp,
+}
+
+// canonical order for game moves, so every node replays them onto the board identically:
+// the game move's action timestamp, then author, then the game move's action hash. validation
+// only allows one link per move, but only the earliest is kept here all the same. links whose
+// move isn't known yet are left out, like links that haven't arrived yet
+pub fn sort_game_move_links(links: Vec<Link>, move_timestamp: impl Fn(&ActionHash) -> Option<Timestamp>) -> Vec<GameMoveLink> {
+    let mut earliest: HashMap<AnyLinkableHash, Link> = HashMap::new();
+    for link in links {
+        match earliest.get(&link.target) {
+            Some(kept) if (&kept.timestamp, &kept.create_link_hash) <= (&link.timestamp, &link.create_link_hash) => {}
+            _ => {
+                earliest.insert(link.target.clone(), link);
+            }
+        }
+    }
+    let mut game_move_links: Vec<GameMoveLink> = earliest
+        .into_values()
+        .filter_map(|link| {
+            let timestamp = move_timestamp(&ActionHash::from(link.target.clone()))?;
+            Some(GameMoveLink { link, timestamp })
+        })
+        .collect();
+    game_move_links.sort_by(|a, b| {
+        (a.timestamp, &a.link.author, &a.link.target).cmp(&(b.timestamp, &b.link.author, &b.link.target))
+    });
+    game_move_links
+}
+
+// the timestamps of the linked game moves, getting the ones that haven't been seen before
+fn get_move_timestamps(links: &[Link]) -> ExternResult<HashMap<ActionHash, Timestamp>> {
+    let mut move_timestamps = MOVE_TIMESTAMPS.lock().unwrap();
+    let missing: Vec<ActionHash> = links
+        .iter()
+        .map(|link| ActionHash::from(link.target.clone()))
+        .filter(|move_hash| !move_timestamps.contains_key(move_hash))
+        .collect();
+    let get_input: Vec<GetInput> = missing
+        .iter()
+        .map(|move_hash| GetInput::new(move_hash.clone().into(), GetOptions::default()))
+        .collect();
+    let records = HDK.with(|hdk| hdk.borrow().get(get_input))?;
+    for record in records.into_iter().flatten() {
+        move_timestamps.insert(record.action_address().clone(), record.action().timestamp());
+    }
+    Ok(move_timestamps.clone())
+}
+
+// all AllGameMoves links, walking the time buckets and returned in canonical order
+pub fn get_all_game_move_links() -> ExternResult<Vec<GameMoveLink>> {
+    let get_links_input: Vec<GetLinksInput> = get_game_move_buckets()?
+        .into_iter()
+        .map(|bucket| Ok(GetLinksInput::new(
+            game_move_bucket_path(bucket).path_entry_hash()?.into(),
+            LinkTypes::AllGameMoves.try_into_filter()?,
+            None,
+        )))
+        .collect::<ExternResult<Vec<GetLinksInput>>>()?;
+    let links = HDK.with(|hdk| hdk.borrow().get_links(get_links_input))?;
+    let links: Vec<Link> = links.into_iter().flatten().collect();
+    let move_timestamps = get_move_timestamps(&links)?;
+    Ok(sort_game_move_links(links, |move_hash| move_timestamps.get(move_hash).copied()))
+}
+
+// an AllGameMoves link together with the game move decoded from its tag
+#[derive(Clone, Debug)]
+pub struct DecodedGameMoveLink {
+    pub link: Link,
+    pub timestamp: Timestamp,   // when the game move was made
+    pub game_move: GameMove,
+}
+
+fn game_move_link_diagnostic(link: &Link, error: String) -> GameMoveLinkDiagnostic {
+    GameMoveLinkDiagnostic {
+        create_link_hash: link.create_link_hash.clone(),
+        target: link.target.clone(),
+        author: link.author.clone(),
+        error,
+    }
+}
+
+// decodes the game move carried in each link tag, keeping the links' order. links whose tag can't
+// be decoded are left out and reported instead, so one bad tag can't break every board read
+pub fn decode_game_move_links(links: Vec<GameMoveLink>) -> (Vec<DecodedGameMoveLink>, Vec<GameMoveLinkDiagnostic>) {
+    let mut game_moves = Vec::with_capacity(links.len());
+    let mut diagnostics = Vec::new();
+    for GameMoveLink { link, timestamp } in links {
+        match GameMove::from_bytes(&link.tag.0) {
+            Ok(game_move) => game_moves.push(DecodedGameMoveLink { link, timestamp, game_move }),
+            Err(e) => diagnostics.push(game_move_link_diagnostic(&link, e.to_string())),
+        }
+    }
+    (game_moves, diagnostics)
+}
+
+// all decodable game moves in canonical order, plus a diagnostic for every link that was skipped
+pub fn get_all_decoded_game_move_links() -> ExternResult<(Vec<DecodedGameMoveLink>, Vec<GameMoveLinkDiagnostic>)> {
+    Ok(decode_game_move_links(get_all_game_move_links()?))
+}
 
 #[hdk_extern]
 pub fn get_all_game_moves(_: ()) -> ExternResult<Vec<Record>> {
-    let path = Path::from("all_game_moves");
-    let links = get_links(path.path_entry_hash()?, LinkTypes::AllGameMoves, None)?;
+    let links = get_all_game_move_links()?;
     let get_input: Vec<GetInput> = links
         .into_iter()
-        .map(|link| GetInput::new(
-            ActionHash::from(link.target).into(),
+        .map(|game_move_link| GetInput::new(
+            ActionHash::from(game_move_link.link.target).into(),
             GetOptions::content(),
         ))
         .collect();
@@ -18,26 +145,65 @@ pub fn get_all_game_moves(_: ()) -> ExternResult<Vec<Record>> {
 }
 
 #[hdk_extern]
-pub fn get_all_game_moves_from_link_tags(_:()) -> ExternResult<Vec<GameMove>> {
-    let path = Path::from("all_game_moves");
-    let path_entry_hash = path.path_entry_hash()?;
-    let links = get_links(path_entry_hash, LinkTypes::AllGameMoves, None)?;
-    // get the bytes from each link tag and make a vector of game moves
-    let game_moves: Vec<GameMove> = links
-        .into_iter()
-        .map(|link| {
-            let bytes = link.tag.0;
-            let game_move = GameMove::from_bytes(&bytes).ok().unwrap();
-            Ok(game_move)
-        })
-        .collect::<ExternResult<Vec<GameMove>>>()?;
-    Ok(game_moves)
+pub fn get_all_game_moves_from_link_tags(_:()) -> ExternResult<GameMovesWithDiagnostics> {
+    let (game_moves, diagnostics) = get_all_decoded_game_move_links()?;
+    Ok(GameMovesWithDiagnostics {
+        game_moves: game_moves.into_iter().map(|decoded| decoded.game_move).collect(),
+        diagnostics,
+    })
+}
+
+// re-checks every AllGameMoves link the way validation does: the tag must decode to the target
+// GameMove entry, the link must be made by the move's author and from the move's time bucket.
+// returns a diagnostic for every link that fails
+#[hdk_extern]
+pub fn audit_game_move_links(_: ()) -> ExternResult<Vec<GameMoveLinkDiagnostic>> {
+    let links: Vec<Link> = get_all_game_move_links()?.into_iter().map(|game_move_link| game_move_link.link).collect();
+    let get_input: Vec<GetInput> = links
+        .iter()
+        .map(|link| GetInput::new(link.create_link_hash.clone().into(), GetOptions::default()))
+        .collect();
+    let create_link_records = HDK.with(|hdk| hdk.borrow().get(get_input))?;
+
+    let mut diagnostics = Vec::new();
+    for (link, create_link_record) in links.iter().zip(create_link_records) {
+        let create_link = match create_link_record.map(|record| record.action().clone()) {
+            Some(Action::CreateLink(create_link)) => create_link,
+            _ => {
+                diagnostics.push(game_move_link_diagnostic(link, String::from("Could not find the CreateLink action")));
+                continue;
+            }
+        };
+        let result = validate_create_link_all_game_moves(
+            create_link.clone(),
+            create_link.base_address,
+            create_link.target_address,
+            create_link.tag,
+        );
+        match result {
+            Ok(ValidateCallbackResult::Valid) => {}
+            Ok(ValidateCallbackResult::Invalid(error)) => diagnostics.push(game_move_link_diagnostic(link, error)),
+            Ok(ValidateCallbackResult::UnresolvedDependencies(_)) => {
+                diagnostics.push(game_move_link_diagnostic(link, String::from("Could not find the target game move")))
+            }
+            Err(e) => diagnostics.push(game_move_link_diagnostic(link, e.to_string())),
+        }
+    }
+    Ok(diagnostics)
+}
+
+// every decodable game move in canonical order as one byte string, see game_moves_to_bytes.
+// offline tools can replay and render the game from this
+#[hdk_extern]
+pub fn export_game_moves(_: ()) -> ExternResult<Vec<u8>> {
+    let (game_moves, _) = get_all_decoded_game_move_links()?;
+    let game_moves: Vec<GameMove> = game_moves.into_iter().map(|decoded| decoded.game_move).collect();
+    Ok(game_moves_to_bytes(&game_moves))
 }
 
 #[hdk_extern]
 pub fn get_number_of_moves(_:()) -> ExternResult<u32> {
-    let path = Path::from("all_game_moves");
-    let links = get_links(path.path_entry_hash()?, LinkTypes::AllGameMoves, None)?;
+    let links = get_all_game_move_links()?;
     Ok(links.len() as u32)
 }
 
@@ -56,4 +222,81 @@ pub fn get_all_my_game_moves(_: ()) -> ExternResult<Vec<Record>> {
     ))?;
     let records: Vec<Record> = records.into_iter().collect();
     Ok(records)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_move(i: u8) -> GameMove {
+        GameMove {
+            changes: vec![PixelChange { x: 1, y: 1, color: Color { r: i, g: 0, b: 0 }, graphic_option: i % 4 }],
+        }
+    }
+
+    // a link to move i by the author, made link_micros after it
+    fn link(i: u8, author: u8, link_micros: i64) -> Link {
+        Link {
+            author: AgentPubKey::from_raw_36(vec![author; 36]),
+            target: ActionHash::from_raw_36(vec![i; 36]).into(),
+            timestamp: Timestamp::from_micros(link_micros),
+            zome_index: ZomeIndex(0),
+            link_type: LinkType(0),
+            tag: LinkTag::new(game_move(i).to_bytes()),
+            create_link_hash: ActionHash::from_raw_36(vec![i.wrapping_add(author).wrapping_mul(7); 36]),
+        }
+    }
+
+    // moves 1 and 2 are made at the same time by different authors, and every link lands later than
+    // the move it points at, in a different order
+    fn move_timestamp(move_hash: &ActionHash) -> Option<Timestamp> {
+        match move_hash.get_raw_36()[0] {
+            1 | 2 => Some(Timestamp::from_micros(10)),
+            9 => None,
+            i => Some(Timestamp::from_micros(i as i64 * 10)),
+        }
+    }
+
+    fn links() -> Vec<Link> {
+        vec![link(1, 5, 90), link(2, 4, 15), link(3, 1, 31), link(4, 1, 80), link(5, 2, 51), link(6, 3, 61)]
+    }
+
+    fn exported(links: Vec<Link>) -> Vec<u8> {
+        let (game_moves, _) = decode_game_move_links(sort_game_move_links(links, move_timestamp));
+        let game_moves: Vec<GameMove> = game_moves.into_iter().map(|decoded| decoded.game_move).collect();
+        game_moves_to_bytes(&game_moves)
+    }
+
+    #[test]
+    fn orders_moves_by_when_they_were_made() {
+        let sorted = sort_game_move_links(links(), move_timestamp);
+        let moves: Vec<u8> = sorted.iter().map(|game_move_link| game_move_link.link.target.get_raw_36()[0]).collect();
+        // 2 comes before 1 by author even though its link is later
+        assert_eq!(moves, vec![2, 1, 3, 4, 5, 6]);
+        assert_eq!(sorted[5].timestamp, Timestamp::from_micros(60));
+    }
+
+    #[test]
+    fn exports_the_same_bytes_whatever_order_the_links_arrive_in() {
+        let expected = exported(links());
+        let mut shuffled = links();
+        for step in [1, 2, 3, 5] {
+            shuffled.rotate_left(step);
+            shuffled.swap(0, step);
+            assert_eq!(exported(shuffled.clone()), expected);
+        }
+        shuffled.reverse();
+        assert_eq!(exported(shuffled), expected);
+    }
+
+    #[test]
+    fn keeps_the_earliest_link_to_each_move() {
+        let mut links = links();
+        links.push(link(3, 1, 10_000));
+        links.push(link(9, 1, 70));
+        let sorted = sort_game_move_links(links, move_timestamp);
+        assert_eq!(sorted.len(), 6);
+        let relinked = sorted.iter().find(|game_move_link| game_move_link.link.target.get_raw_36()[0] == 3).unwrap();
+        assert_eq!(relinked.link.timestamp, Timestamp::from_micros(31));
+        assert_eq!(exported(sorted.into_iter().map(|game_move_link| game_move_link.link).collect()), exported(self::links()));
+    }
+}
@@ -1,13 +1,15 @@
 use hdk::prelude::*;
 use fractal_tribute_integrity::*;
 use crate::all_game_moves::*;
+use crate::board_checkpoint::*;
+use crate::board_images::*;
 use ethers_core::types::U256;
 
 #[hdk_extern]
 pub fn get_latest_board(_: ()) -> ExternResult<BoardWithMetadata> {
-    let game_moves = get_all_game_moves_from_link_tags(())?;
+    let (game_moves, diagnostics) = get_all_decoded_game_move_links()?;
 
-    let board = Board::reconstruct_from_game_moves(&game_moves);
+    let board = board_at_move_count(&game_moves, game_moves.len())?;
     let bytes = board.to_bytes();
     let svg = board.generate_svg();
     let complete_svg = board.generate_svg_with_defs();
@@ -21,13 +23,14 @@ pub fn get_latest_board(_: ()) -> ExternResult<BoardWithMetadata> {
             bytes,
             creator,
             creation_hash,
+            diagnostics,
         }
     )
 }
 
 #[hdk_extern]
 pub fn get_board_at_move(move_action_hash: ActionHash) -> ExternResult<BoardWithMetadata> {
-    let board = _get_board_at_move(move_action_hash.clone())?;
+    let (board, diagnostics) = _get_board_at_move(move_action_hash.clone())?;
     let bytes = board.to_bytes();
     let svg = board.generate_svg();
     let complete_svg = board.generate_svg_with_defs();
@@ -40,35 +43,22 @@ pub fn get_board_at_move(move_action_hash: ActionHash) -> ExternResult<BoardWith
             bytes,
             creator,
             creation_hash: move_action_hash,
+            diagnostics,
         }
     )
 }
 
-pub fn _get_board_at_move(move_action_hash: ActionHash) -> ExternResult<Board> {
-    let path = hdk::hash_path::path::Path::from("all_game_moves");
-    let links: Vec<Link> = get_links(path.path_entry_hash()?, LinkTypes::AllGameMoves, None)?;
+// the board up to and including the move, along with any game move links skipped while replaying it
+pub fn _get_board_at_move(move_action_hash: ActionHash) -> ExternResult<(Board, Vec<GameMoveLinkDiagnostic>)> {
+    let (game_moves, diagnostics) = get_all_decoded_game_move_links()?;
 
-    // truncate the links
-    let mut links = links.clone();
-    if let Some(pos) = links.clone().iter().position(|link| link.target.clone().into_action_hash().unwrap().eq(&move_action_hash)) {
-        links.truncate(pos + 1)
-    } else {
-        return Err(wasm_error!("Could not find a game move for that action hash"));
-    }
-
-    // get the bytes from each link tag and make a vector of game moves
-    let game_moves: Vec<GameMove> = links.clone()
-        .into_iter()
-        .map(|link| {
-            let bytes = link.tag.0;
-            let game_move = GameMove::from_bytes(&bytes).ok().unwrap();
-            Ok(game_move)
-        })
-        .collect::<ExternResult<Vec<GameMove>>>()?;
-
-    let board = Board::reconstruct_from_game_moves(&game_moves);
+    // replay up to and including the move
+    let position = match game_moves.iter().position(|decoded| decoded.link.target.clone().into_action_hash() == Some(move_action_hash.clone())) {
+        Some(position) => position,
+        None => return Err(wasm_error!("Could not find a game move for that action hash")),
+    };
 
-    Ok(board)
+    Ok((board_at_move_count(&game_moves, position + 1)?, diagnostics))
 }
 
 #[hdk_extern]
@@ -111,7 +101,7 @@ pub fn _get_board_from_link(base: ExternalHash) -> ExternResult<Board> {
         return Err(wasm_error!("No game moves found for that token id"));
     }
     let action_hash = links[0].target.clone();
-    let board = _get_board_at_move(action_hash.into())?;
+    let (board, _) = _get_board_at_move(action_hash.into())?;
     Ok(board)
 }
 
@@ -126,28 +116,36 @@ pub fn get_boards_from_links(link_bases: Vec<ExternalHash>) -> ExternResult<Vec<
     Ok(boards)
 }
 
-fn token_id_to_board(token_id: U256) -> ExternResult<Board> {
+// the base of the TokenIdToGameMove link for a token id
+pub fn token_id_to_link_base(token_id: U256) -> ExternResult<ExternalHash> {
     let mut link_base = [0u8; 39];
     link_base[..3].copy_from_slice(&[132, 47, 36]);
     token_id.to_big_endian(&mut link_base[3..35]);
     link_base[35..].copy_from_slice(&[0u8; 4]);
     debug!("link_base: {:?}", link_base);
-    let link_base = HoloHash::from_raw_39(link_base.to_vec()).map_err(|_| wasm_error!("Could not parse token id"))?;
+    HoloHash::from_raw_39(link_base.to_vec()).map_err(|_| wasm_error!("Could not parse token id"))
+}
+
+fn token_id_to_board(token_id: U256) -> ExternResult<Board> {
+    let link_base = token_id_to_link_base(token_id)?;
     let board = _get_board_from_link(link_base)?;
     Ok(board)
 }
 
-// a function that will create a metadata json for an nft, given a token id
+#[hdk_entry_helper]
+#[derive(Clone)]
+pub struct TokenIdToImageInput {
+    pub token_id: String,
+    pub board_size: String,
+    pub format: String,
+}
+
+// the image of an nft as bytes, for serving or saving rather than embedding in its metadata
 #[hdk_extern]
-fn token_id_to_metadata(str: String) -> ExternResult<String> {
-    let token_id = U256::from_dec_str(&str).map_err(|_| wasm_error!("Could not parse token id"))?;
+fn token_id_to_image(input: TokenIdToImageInput) -> ExternResult<BoardImage> {
+    let token_id = U256::from_dec_str(&input.token_id).map_err(|_| wasm_error!("Could not parse token id"))?;
+    let board_size = parse_board_size(&input.board_size)?;
+    let format = parse_image_format(&input.format)?;
     let board = token_id_to_board(token_id)?;
-    let svg = board.generate_svg_data_uri();
-    let metadata = Metadata {
-        name: "Fractal Tribute".to_string(),
-        description: "A collaborative art game".to_string(),
-        image: svg,
-    };
-    let json = serde_json::to_string(&metadata).map_err(|_| wasm_error!("Could not serialize metadata"))?;
-    Ok(json)
+    board_image(board, board_size, format)
 }
@@ -0,0 +1,144 @@
+use hdk::prelude::*;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use fractal_tribute_integrity::*;
+use image::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame};
+use std::str::FromStr;
+use crate::all_game_moves::*;
+use crate::board_checkpoint::*;
+use crate::board_images::*;
+
+// every frame is a full render, so keep a single call within what a zome call can afford
+pub const MAX_ANIMATION_FRAMES: usize = 500;
+
+#[hdk_entry_helper]
+#[derive(Clone, Copy, PartialEq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl FromStr for AnimationFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Gif" => Ok(AnimationFormat::Gif),
+            "Apng" => Ok(AnimationFormat::Apng),
+            _ => Err(()),
+        }
+    }
+}
+
+#[hdk_entry_helper]
+#[derive(Clone)]
+pub struct BoardAnimationInput {
+    pub from_move: u32,        // moves applied to the first frame
+    pub to_move: Option<u32>,  // moves applied to the last frame, defaults to all of them
+    pub stride: u32,           // moves between frames
+    pub frame_delay_ms: u32,
+    pub board_size: String,
+    pub format: String,
+}
+
+// the board after from_move moves, then after every stride moves up to to_move. the last frame is
+// always the board at to_move, even when the stride doesn't land on it
+pub fn timelapse_boards(mut board: Board, game_moves: &[GameMove], stride: usize) -> Vec<Board> {
+    let stride = stride.max(1);
+    let mut boards = vec![board.clone()];
+    for (index, game_move) in game_moves.iter().enumerate() {
+        board.apply_game_move(game_move);
+        if (index + 1) % stride == 0 || index + 1 == game_moves.len() {
+            boards.push(board.clone());
+        }
+    }
+    boards
+}
+
+// renders each board and encodes them as one animation that loops forever. consecutive boards
+// share most of their tiles, so each frame only redraws the tiles that changed
+pub fn encode_animation(
+    boards: &[Board],
+    mask_images: &MaskSet,
+    tile_size: u32,
+    frame_delay_ms: u32,
+    format: AnimationFormat,
+) -> Result<Vec<u8>, String> {
+    let first_board = boards.first().ok_or(String::from("An animation needs at least one frame"))?;
+    let mut renderer = IncrementalRenderer::new(first_board.clone(), mask_images, tile_size);
+    let mut bytes = Vec::new();
+    match format {
+        AnimationFormat::Gif => {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            encoder.set_repeat(Repeat::Infinite).map_err(|e| e.to_string())?;
+            for board in boards {
+                renderer.render_board(board.clone(), mask_images, tile_size);
+                let frame = Frame::from_parts(renderer.canvas().clone(), 0, 0, Delay::from_numer_denom_ms(frame_delay_ms, 1));
+                encoder.encode_frame(frame).map_err(|e| e.to_string())?;
+            }
+        }
+        AnimationFormat::Apng => {
+            let (width, height) = renderer.canvas().dimensions();
+            let mut encoder = png::Encoder::new(&mut bytes, width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(boards.len() as u32, 0).map_err(|e| e.to_string())?;
+            // delays are stored as a fraction of a second with 16 bit parts
+            encoder.set_frame_delay(frame_delay_ms.min(u16::MAX as u32) as u16, 1000).map_err(|e| e.to_string())?;
+            let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+            for board in boards {
+                renderer.render_board(board.clone(), mask_images, tile_size);
+                writer.write_image_data(renderer.canvas()).map_err(|e| e.to_string())?;
+            }
+            writer.finish().map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(bytes)
+}
+
+// renders a range of the move history as an animated GIF or APNG data URI. not in the unrestricted
+// grant, a long range at a large size is too much work to hand to anyone who asks
+#[hdk_extern]
+pub fn board_history_to_animation(input: BoardAnimationInput) -> ExternResult<String> {
+    let board_size = input.board_size.parse::<BoardSize>().map_err(|_| {
+        wasm_error!(format!("Board size must be Small, Large or a number of pixels up to {}", MAX_BOARD_PIXELS))
+    })?;
+    let format = input.format.parse::<AnimationFormat>().map_err(|_| {
+        wasm_error!("Invalid animation format provided")
+    })?;
+
+    let (game_moves, _) = get_all_decoded_game_move_links()?;
+    let from_move = input.from_move as usize;
+    let to_move = input.to_move.map_or(game_moves.len(), |to_move| to_move as usize);
+    if from_move > to_move || to_move > game_moves.len() {
+        return Err(wasm_error!(format!(
+            "Animation range must be within 0 to {} moves",
+            game_moves.len()
+        )));
+    }
+    let stride = (input.stride as usize).max(1);
+    if (to_move - from_move).div_ceil(stride) + 1 > MAX_ANIMATION_FRAMES {
+        return Err(wasm_error!(format!(
+            "Animations are limited to {} frames, use a larger stride or a smaller range",
+            MAX_ANIMATION_FRAMES
+        )));
+    }
+
+    let start_board = board_at_move_count(&game_moves, from_move)?;
+    let moves: Vec<GameMove> = game_moves[from_move..to_move]
+        .iter()
+        .map(|decoded| decoded.game_move.clone())
+        .collect();
+    let boards = timelapse_boards(start_board.clone(), &moves, stride);
+
+    let mask_images = get_mask_images(&board_size).map_err(|e| wasm_error!(e))?;
+    let tile_size = tile_size_for(board_size, &start_board).map_err(|e| wasm_error!(e))?;
+    let bytes = encode_animation(&boards, &mask_images, tile_size, input.frame_delay_ms, format)
+        .map_err(|e| wasm_error!(e))?;
+
+    let mime_type = match format {
+        AnimationFormat::Gif => "image/gif",
+        AnimationFormat::Apng => "image/apng",
+    };
+    Ok(format!("data:{};base64,{}", mime_type, STANDARD.encode(bytes)))
+}
@@ -0,0 +1,287 @@
+use hdk::prelude::*;
+use fractal_tribute_integrity::*;
+use fractal_tribute_integrity::dna_properties::_get_dna_properties;
+use crate::all_game_moves::*;
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+// the published checkpoints as (move count, checkpoint action hash), newest first
+fn get_board_checkpoint_links() -> ExternResult<Vec<(u32, ActionHash)>> {
+    let links = get_links(board_checkpoints_path().path_entry_hash()?, LinkTypes::BoardCheckpoints, None)?;
+    let mut checkpoints: Vec<(u32, ActionHash)> = links
+        .into_iter()
+        .filter_map(|link| {
+            let move_count = u32::from_be_bytes(link.tag.0.as_slice().try_into().ok()?);
+            Some((move_count, ActionHash::from(link.target)))
+        })
+        .collect();
+    checkpoints.sort_by_key(|(move_count, _)| std::cmp::Reverse(*move_count));
+    Ok(checkpoints)
+}
+
+// checkpoints never change, so each one is only fetched once
+static BOARD_CHECKPOINTS: Lazy<Mutex<HashMap<ActionHash, BoardCheckpoint>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// how many of the newest checkpoints a read tries before replaying every move from the start
+const MAX_CHECKPOINT_CANDIDATES: usize = 3;
+
+fn get_board_checkpoint(checkpoint_hash: ActionHash) -> ExternResult<Option<BoardCheckpoint>> {
+    if let Some(checkpoint) = BOARD_CHECKPOINTS.lock().unwrap().get(&checkpoint_hash) {
+        return Ok(Some(checkpoint.clone()));
+    }
+    let checkpoint: Option<BoardCheckpoint> = match get(checkpoint_hash.clone(), GetOptions::default())? {
+        Some(record) => record.entry().to_app_option().ok().flatten(),
+        None => None,
+    };
+    if let Some(checkpoint) = &checkpoint {
+        BOARD_CHECKPOINTS.lock().unwrap().insert(checkpoint_hash, checkpoint.clone());
+    }
+    Ok(checkpoint)
+}
+
+// whether a checkpoint and every checkpoint before it replayed the moves we see, in the same
+// order. validation only checks each checkpoint against the one before it, so one that replayed
+// other moves would carry over into every checkpoint after it. the checkpoints walked through are
+// added to rejected when it doesn't match, so other candidates stop as soon as they reach one
+pub fn checkpoint_chain_matches<F>(
+    move_hashes: &[ActionHash],
+    checkpoint_hash: &ActionHash,
+    rejected: &mut HashSet<ActionHash>,
+    mut get_checkpoint: F,
+) -> ExternResult<bool>
+where
+    F: FnMut(ActionHash) -> ExternResult<Option<BoardCheckpoint>>,
+{
+    let mut walked = Vec::new();
+    let mut next = checkpoint_hash.clone();
+    // where the checkpoint after this one started, which this one has to end at
+    let mut expected_end = None;
+    let matches = loop {
+        if rejected.contains(&next) {
+            break false;
+        }
+        walked.push(next.clone());
+        let checkpoint = match get_checkpoint(next)? {
+            Some(checkpoint) => checkpoint,
+            None => break false,
+        };
+        let end = checkpoint.move_count as usize;
+        let start = match end.checked_sub(checkpoint.move_hashes.len()) {
+            Some(start) => start,
+            None => break false,
+        };
+        if expected_end.is_some_and(|expected_end| expected_end != end) {
+            break false;
+        }
+        if move_hashes.get(start..end) != Some(&checkpoint.move_hashes[..]) {
+            break false;
+        }
+        match checkpoint.previous_checkpoint {
+            Some(previous_checkpoint_hash) => next = previous_checkpoint_hash,
+            None => break start == 0,
+        }
+        expected_end = Some(start);
+    };
+    if !matches {
+        rejected.extend(walked);
+    }
+    Ok(matches)
+}
+
+// the newest checkpoint covering at most the first move_count of the canonically ordered moves.
+// a checkpoint is only used if the moves it and the checkpoints before it replayed are the same
+// ones we see, in the same order
+pub fn get_nearest_board_checkpoint(game_moves: &[DecodedGameMoveLink], move_count: usize) -> ExternResult<Option<(ActionHash, BoardCheckpoint)>> {
+    let move_hashes: Vec<ActionHash> = game_moves
+        .iter()
+        .take(move_count)
+        .map(|decoded| ActionHash::from(decoded.link.target.clone()))
+        .collect();
+    let candidates = get_board_checkpoint_links()?
+        .into_iter()
+        .filter(|(checkpoint_move_count, _)| *checkpoint_move_count as usize <= move_count)
+        .take(MAX_CHECKPOINT_CANDIDATES);
+    let mut rejected = HashSet::new();
+    for (_, checkpoint_hash) in candidates {
+        if checkpoint_chain_matches(&move_hashes, &checkpoint_hash, &mut rejected, get_board_checkpoint)? {
+            if let Some(checkpoint) = get_board_checkpoint(checkpoint_hash.clone())? {
+                return Ok(Some((checkpoint_hash, checkpoint)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+// the board after the first move_count canonically ordered moves, replayed from the nearest checkpoint
+pub fn board_at_move_count(game_moves: &[DecodedGameMoveLink], move_count: usize) -> ExternResult<Board> {
+    let (board_width, board_height) = _get_dna_properties(())?.board_dimensions().map_err(|e| wasm_error!(e))?;
+    let (mut board, start) = match get_nearest_board_checkpoint(game_moves, move_count)? {
+        Some((_, checkpoint)) => (
+            Board::from_checkpoint_bytes(board_width, board_height, &checkpoint.board_bytes).map_err(|e| wasm_error!(e))?,
+            checkpoint.move_count as usize,
+        ),
+        None => (Board::new(board_width, board_height), 0),
+    };
+    for decoded in &game_moves[start..move_count] {
+        board.apply_game_move(&decoded.game_move);
+    }
+    Ok(board)
+}
+
+// publishes the next checkpoint if at least BOARD_CHECKPOINT_INTERVAL moves have been made since
+// the latest one. only one checkpoint is created per call. links with undecodable tags are skipped
+#[hdk_extern]
+pub fn create_board_checkpoint(_: ()) -> ExternResult<Option<Record>> {
+    let (game_moves, _) = get_all_decoded_game_move_links()?;
+    let (board_width, board_height) = _get_dna_properties(())?.board_dimensions().map_err(|e| wasm_error!(e))?;
+
+    let (mut board, previous_checkpoint, previous_move_count) = match get_nearest_board_checkpoint(&game_moves, game_moves.len())? {
+        Some((checkpoint_hash, checkpoint)) => (
+            Board::from_checkpoint_bytes(board_width, board_height, &checkpoint.board_bytes).map_err(|e| wasm_error!(e))?,
+            Some(checkpoint_hash),
+            checkpoint.move_count as usize,
+        ),
+        None => (Board::new(board_width, board_height), None, 0),
+    };
+
+    let move_count = previous_move_count + BOARD_CHECKPOINT_INTERVAL as usize;
+    if game_moves.len() < move_count {
+        return Ok(None);
+    }
+
+    let mut move_hashes = Vec::with_capacity(BOARD_CHECKPOINT_INTERVAL as usize);
+    for decoded in &game_moves[previous_move_count..move_count] {
+        board.apply_game_move(&decoded.game_move);
+        move_hashes.push(ActionHash::from(decoded.link.target.clone()));
+    }
+
+    let board_checkpoint = BoardCheckpoint {
+        board_bytes: board.to_checkpoint_bytes(),
+        last_move_hash: ActionHash::from(game_moves[move_count - 1].link.target.clone()),
+        move_count: move_count as u32,
+        previous_checkpoint,
+        move_hashes,
+    };
+    let board_checkpoint_hash = create_entry(&EntryTypes::BoardCheckpoint(board_checkpoint.clone()))?;
+    let record = get(board_checkpoint_hash.clone(), GetOptions::default())?
+        .ok_or(
+            wasm_error!(
+                WasmErrorInner::Guest(String::from("Could not find the newly created BoardCheckpoint"))
+            ),
+        )?;
+
+    create_link(
+        board_checkpoints_path().path_entry_hash()?,
+        board_checkpoint_hash,
+        LinkTypes::BoardCheckpoints,
+        LinkTag::new(board_checkpoint.move_count.to_be_bytes().to_vec()),
+    )?;
+
+    Ok(Some(record))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn move_hashes(count: usize) -> Vec<ActionHash> {
+        (0..count).map(|i| ActionHash::from_raw_36([(i % 256) as u8, (i / 256) as u8].repeat(18))).collect()
+    }
+
+    fn checkpoint_hash(move_count: u32) -> ActionHash {
+        ActionHash::from_raw_36(vec![(move_count / BOARD_CHECKPOINT_INTERVAL) as u8; 36])
+    }
+
+    // checkpoints over every BOARD_CHECKPOINT_INTERVAL of the moves, each from the one before
+    fn checkpoint_chain(moves: &[ActionHash], count: u32) -> HashMap<ActionHash, BoardCheckpoint> {
+        let interval = BOARD_CHECKPOINT_INTERVAL as usize;
+        (1..=count)
+            .map(|i| {
+                let move_count = i * BOARD_CHECKPOINT_INTERVAL;
+                let window = moves[(i as usize - 1) * interval..i as usize * interval].to_vec();
+                let checkpoint = BoardCheckpoint {
+                    board_bytes: vec![],
+                    last_move_hash: window.last().unwrap().clone(),
+                    move_count,
+                    previous_checkpoint: (i > 1).then(|| checkpoint_hash(move_count - BOARD_CHECKPOINT_INTERVAL)),
+                    move_hashes: window,
+                };
+                (checkpoint_hash(move_count), checkpoint)
+            })
+            .collect()
+    }
+
+    fn matches(moves: &[ActionHash], checkpoints: &HashMap<ActionHash, BoardCheckpoint>, move_count: u32) -> bool {
+        let rejected = &mut HashSet::new();
+        checkpoint_chain_matches(moves, &checkpoint_hash(move_count), rejected, |hash| Ok(checkpoints.get(&hash).cloned())).unwrap()
+    }
+
+    #[test]
+    fn follows_the_chain_back_to_the_first_checkpoint() {
+        let moves = move_hashes(350);
+        let checkpoints = checkpoint_chain(&moves, 3);
+        assert!(matches(&moves, &checkpoints, 300));
+        assert!(matches(&moves[..300], &checkpoints, 300));
+        assert!(!matches(&moves[..299], &checkpoints, 300));
+    }
+
+    #[test]
+    fn rejects_checkpoints_built_on_other_moves() {
+        let moves = move_hashes(300);
+        let checkpoints = checkpoint_chain(&moves, 3);
+
+        // a move we see that the first checkpoint never replayed poisons every checkpoint after it
+        let mut other_moves = moves.clone();
+        other_moves.swap(10, 11);
+        assert!(!matches(&other_moves, &checkpoints, 300));
+        assert!(!matches(&other_moves, &checkpoints, 100));
+        assert!(matches(&other_moves[..], &checkpoint_chain(&other_moves, 3), 300));
+    }
+
+    #[test]
+    fn rejects_broken_chains() {
+        let moves = move_hashes(300);
+
+        let mut missing_previous = checkpoint_chain(&moves, 3);
+        missing_previous.remove(&checkpoint_hash(100));
+        assert!(!matches(&moves, &missing_previous, 300));
+
+        // the first checkpoint has to start from the first move
+        let mut not_from_the_start = checkpoint_chain(&moves, 3);
+        not_from_the_start.get_mut(&checkpoint_hash(200)).unwrap().previous_checkpoint = None;
+        assert!(!matches(&moves, &not_from_the_start, 300));
+
+        // checkpoints have to cover the moves up to the one after them
+        let mut gap = checkpoint_chain(&moves, 3);
+        gap.get_mut(&checkpoint_hash(100)).unwrap().move_count = 90;
+        assert!(!matches(&moves, &gap, 300));
+    }
+
+    #[test]
+    fn rejects_more_move_hashes_than_moves() {
+        let moves = move_hashes(100);
+        let mut checkpoints = checkpoint_chain(&moves, 1);
+        checkpoints.get_mut(&checkpoint_hash(100)).unwrap().move_count = 50;
+        assert!(!matches(&moves, &checkpoints, 100));
+    }
+
+    #[test]
+    fn stops_at_checkpoints_already_rejected() {
+        let moves = move_hashes(300);
+        let mut checkpoints = checkpoint_chain(&moves, 3);
+        checkpoints.get_mut(&checkpoint_hash(100)).unwrap().move_hashes.swap(0, 1);
+
+        let mut rejected = HashSet::new();
+        let mut fetched = 0;
+        let mut get_checkpoint = |hash: ActionHash| {
+            fetched += 1;
+            Ok(checkpoints.get(&hash).cloned())
+        };
+        assert!(!checkpoint_chain_matches(&moves, &checkpoint_hash(300), &mut rejected, &mut get_checkpoint).unwrap());
+        assert_eq!(rejected.len(), 3);
+        // the second newest goes back through the same bad checkpoint, so it isn't fetched again
+        assert!(!checkpoint_chain_matches(&moves, &checkpoint_hash(200), &mut rejected, &mut get_checkpoint).unwrap());
+        assert_eq!(fetched, 3);
+    }
+}
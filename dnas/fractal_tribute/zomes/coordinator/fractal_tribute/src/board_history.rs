@@ -0,0 +1,214 @@
+use hdk::prelude::*;
+use fractal_tribute_integrity::*;
+use crate::all_game_moves::*;
+use crate::board_checkpoint::*;
+
+// the most frames returned by one get_board_history call. full frames are much larger than diffs
+pub const MAX_BOARD_HISTORY_FRAMES: usize = 100;
+pub const MAX_BOARD_HISTORY_DIFFS: usize = 1000;
+
+// applies a game move and returns the tiles whose value it changed, in the order they were first touched
+pub fn apply_game_move_with_changes(board: &mut Board, game_move: &GameMove) -> Vec<TileChange> {
+    let mut touched = Vec::new();
+    for change in &game_move.changes {
+        if touched.iter().any(|(x, y, _)| *x == change.x && *y == change.y) {
+            continue;
+        }
+        if let Some(tile) = board.tiles.get(change.y).and_then(|row| row.get(change.x)) {
+            touched.push((change.x, change.y, *tile));
+        }
+    }
+    board.apply_game_move(game_move);
+    touched
+        .into_iter()
+        .filter_map(|(x, y, before)| {
+            let tile = board.tiles[y][x];
+            (tile != before).then_some(TileChange { x: x as u32, y: y as u32, tile })
+        })
+        .collect()
+}
+
+// the moves a get_board_history call replays, as (from_move, page_end, to_move). the page ends
+// early when the range is longer than the format's page size
+pub fn board_history_range(input: &BoardHistoryInput, move_count: usize) -> ExternResult<(usize, usize, usize)> {
+    let from_move = input.from_move as usize;
+    let to_move = input.to_move.map_or(move_count, |to_move| to_move as usize);
+    if from_move > to_move || to_move > move_count {
+        return Err(wasm_error!(format!(
+            "Board history range must be within 0 to {} moves",
+            move_count
+        )));
+    }
+
+    let page_size = match input.format {
+        BoardHistoryFormat::Frames => MAX_BOARD_HISTORY_FRAMES,
+        BoardHistoryFormat::Diffs => MAX_BOARD_HISTORY_DIFFS,
+    };
+    Ok((from_move, to_move.min(from_move + page_size), to_move))
+}
+
+// applies the moves to a board that already has from_move moves on it, with a frame for each
+pub fn board_history_frames(
+    board: &mut Board,
+    game_moves: &[DecodedGameMoveLink],
+    from_move: usize,
+    format: BoardHistoryFormat,
+) -> Vec<BoardHistoryFrame> {
+    let mut frames = Vec::with_capacity(game_moves.len());
+    for (index, decoded) in game_moves.iter().enumerate() {
+        let changed_tiles = apply_game_move_with_changes(board, &decoded.game_move);
+        let (bytes, changed_tiles) = match format {
+            BoardHistoryFormat::Frames => (Some(board.to_bytes()), vec![]),
+            BoardHistoryFormat::Diffs => (None, changed_tiles),
+        };
+        frames.push(BoardHistoryFrame {
+            move_count: (from_move + index + 1) as u32,
+            move_hash: ActionHash::from(decoded.link.target.clone()),
+            author: decoded.link.author.clone(),
+            timestamp: decoded.timestamp,
+            bytes,
+            changed_tiles,
+        });
+    }
+    frames
+}
+
+// replays the canonically ordered moves once, from the board after from_move moves, returning a
+// frame or diff for each move up to to_move. long ranges are paged, see BoardHistory::next_move
+#[hdk_extern]
+pub fn get_board_history(input: BoardHistoryInput) -> ExternResult<BoardHistory> {
+    let (game_moves, diagnostics) = get_all_decoded_game_move_links()?;
+    let (from_move, page_end, to_move) = board_history_range(&input, game_moves.len())?;
+
+    let mut board = board_at_move_count(&game_moves, from_move)?;
+    let start_bytes = board.to_bytes();
+    let frames = board_history_frames(&mut board, &game_moves[from_move..page_end], from_move, input.format);
+
+    Ok(BoardHistory {
+        width: board.width as u32,
+        height: board.height as u32,
+        start_bytes,
+        frames,
+        next_move: (page_end < to_move).then_some(page_end as u32),
+        diagnostics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fractal_tribute_integrity::board::Tile;
+
+    const RED: Color = Color { r: 255, g: 0, b: 0 };
+    const BLUE: Color = Color { r: 0, g: 0, b: 255 };
+
+    fn paint(x: usize, y: usize, color: Color, graphic_option: u8) -> PixelChange {
+        PixelChange { x, y, color, graphic_option }
+    }
+
+    fn tile(color: Color, graphic_option: u8) -> Tile {
+        Tile { color: Some(color), graphic_option: Some(graphic_option) }
+    }
+
+    const EMPTY: Tile = Tile { color: None, graphic_option: None };
+
+    fn decoded(i: u8, game_move: GameMove) -> DecodedGameMoveLink {
+        DecodedGameMoveLink {
+            link: Link {
+                author: AgentPubKey::from_raw_36(vec![1; 36]),
+                target: ActionHash::from_raw_36(vec![i; 36]).into(),
+                timestamp: Timestamp::from_micros(i as i64),
+                zome_index: ZomeIndex(0),
+                link_type: LinkType(0),
+                tag: LinkTag::new(game_move.to_bytes()),
+                create_link_hash: ActionHash::from_raw_36(vec![i; 36]),
+            },
+            timestamp: Timestamp::from_micros(i as i64),
+            game_move,
+        }
+    }
+
+    fn range(from_move: u32, to_move: Option<u32>, format: BoardHistoryFormat, move_count: usize) -> Option<(usize, usize, usize)> {
+        board_history_range(&BoardHistoryInput { from_move, to_move, format }, move_count).ok()
+    }
+
+    #[test]
+    fn diffs_only_the_tiles_a_move_changed() {
+        let mut board = Board::new(4, 4);
+        let changes = apply_game_move_with_changes(&mut board, &GameMove { changes: vec![paint(1, 2, RED, 3), paint(0, 0, BLUE, 1)] });
+        assert_eq!(changes, vec![
+            TileChange { x: 1, y: 2, tile: tile(RED, 3) },
+            TileChange { x: 0, y: 0, tile: tile(BLUE, 1) },
+        ]);
+
+        // repainting a tile the same way, or erasing an empty one, changes nothing
+        let changes = apply_game_move_with_changes(&mut board, &GameMove { changes: vec![paint(1, 2, RED, 3), PixelChange::erase(3, 3)] });
+        assert_eq!(changes, vec![]);
+
+        // a tile touched twice is reported once, with where it ended up, and changes off the board are skipped
+        let changes = apply_game_move_with_changes(&mut board, &GameMove {
+            changes: vec![paint(2, 2, RED, 1), paint(0, 0, RED, 1), paint(2, 2, BLUE, 2), paint(9, 9, RED, 1)],
+        });
+        assert_eq!(changes, vec![
+            TileChange { x: 2, y: 2, tile: tile(BLUE, 2) },
+            TileChange { x: 0, y: 0, tile: tile(RED, 1) },
+        ]);
+
+        // painting a tile and erasing it again in one move leaves nothing to report
+        let changes = apply_game_move_with_changes(&mut board, &GameMove { changes: vec![paint(3, 0, RED, 1), PixelChange::erase(3, 0)] });
+        assert_eq!(changes, vec![]);
+    }
+
+    #[test]
+    fn diffs_erases() {
+        let mut board = Board::new(4, 4);
+        apply_game_move_with_changes(&mut board, &GameMove { changes: vec![paint(1, 1, RED, 3), paint(2, 1, RED, 3)] });
+        let changes = apply_game_move_with_changes(&mut board, &GameMove { changes: vec![PixelChange::erase(1, 1), PixelChange::erase(0, 1)] });
+        assert_eq!(changes, vec![TileChange { x: 1, y: 1, tile: EMPTY }]);
+        assert_eq!(board.tiles[1][1], EMPTY);
+        assert_eq!(board.tiles[1][2], tile(RED, 3));
+    }
+
+    #[test]
+    fn replays_frames_and_diffs_from_the_starting_board() {
+        let game_moves: Vec<DecodedGameMoveLink> = (0..3)
+            .map(|i| decoded(i, GameMove { changes: vec![paint(i as usize, 0, RED, i)] }))
+            .collect();
+        let start = Board::reconstruct_from_game_moves(4, 4, &[game_moves[0].game_move.clone()]);
+
+        let mut board = start.clone();
+        let frames = board_history_frames(&mut board, &game_moves[1..], 1, BoardHistoryFormat::Frames);
+        assert_eq!(frames.iter().map(|frame| frame.move_count).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(frames[1].move_hash, ActionHash::from_raw_36(vec![2; 36]));
+        let all_moves: Vec<GameMove> = game_moves.iter().map(|decoded| decoded.game_move.clone()).collect();
+        assert_eq!(frames[1].bytes, Some(Board::reconstruct_from_game_moves(4, 4, &all_moves).to_bytes()));
+        assert!(frames.iter().all(|frame| frame.changed_tiles.is_empty()));
+
+        let mut board = start;
+        let diffs = board_history_frames(&mut board, &game_moves[1..], 1, BoardHistoryFormat::Diffs);
+        assert_eq!(diffs[0].changed_tiles, vec![TileChange { x: 1, y: 0, tile: tile(RED, 1) }]);
+        assert!(diffs.iter().all(|frame| frame.bytes.is_none()));
+    }
+
+    #[test]
+    fn pages_long_ranges() {
+        let frames = MAX_BOARD_HISTORY_FRAMES;
+        let diffs = MAX_BOARD_HISTORY_DIFFS;
+        // to_move defaults to every move
+        assert_eq!(range(0, None, BoardHistoryFormat::Frames, 50), Some((0, 50, 50)));
+        assert_eq!(range(0, None, BoardHistoryFormat::Frames, frames + 1), Some((0, frames, frames + 1)));
+        assert_eq!(range(0, None, BoardHistoryFormat::Diffs, frames + 1), Some((0, frames + 1, frames + 1)));
+        assert_eq!(range(10, None, BoardHistoryFormat::Diffs, 2 * diffs), Some((10, diffs + 10, 2 * diffs)));
+        // a range exactly one page long isn't cut short
+        assert_eq!(range(5, Some(frames as u32 + 5), BoardHistoryFormat::Frames, 500), Some((5, frames + 5, frames + 5)));
+        // an empty range
+        assert_eq!(range(7, Some(7), BoardHistoryFormat::Frames, 7), Some((7, 7, 7)));
+    }
+
+    #[test]
+    fn rejects_ranges_outside_the_moves() {
+        assert_eq!(range(8, Some(7), BoardHistoryFormat::Frames, 10), None);
+        assert_eq!(range(0, Some(11), BoardHistoryFormat::Frames, 10), None);
+        assert_eq!(range(11, None, BoardHistoryFormat::Diffs, 10), None);
+    }
+}
@@ -3,177 +3,276 @@ use fractal_tribute_integrity::*;
 use image::{ImageBuffer, Rgba};
 use image::png::PngEncoder;
 use std::collections::HashMap;
-use std::io::Cursor;
-use std::sync::Once;
 use std::str::FromStr;
 use std::collections::VecDeque;
 use std::hash::{Hash, Hasher};
 use std::collections::hash_map::DefaultHasher;
 use std::sync::Mutex;
-use once_cell::sync::OnceCell;
-use image::buffer::ConvertBuffer;
+use once_cell::sync::{Lazy, OnceCell};
+use std::collections::hash_map::Entry;
+use fractal_tribute_integrity::board::Tile;
+use crate::board_history::apply_game_move_with_changes;
+use crate::mask_assets::*;
+use crate::render_cache::RENDER_CACHE;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use image::imageops::FilterType;
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::TreeParsing;
+use std::sync::Arc;
+
+// the pattern masks in the format of crate::mask_assets, written by cli_tools
+const SMALL_MASKS: [&'static [u8]; GRAPHIC_OPTIONS] = [
+    include_bytes!("../../../../../../pattern-masks/small/1.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/2.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/3.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/4.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/5.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/6.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/7.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/8.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/9.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/10.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/11.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/12.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/13.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/14.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/15.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/16.mask"),
+    include_bytes!("../../../../../../pattern-masks/small/17.mask"),
+];
+
+const LARGE_MASKS: [&'static [u8]; GRAPHIC_OPTIONS] = [
+    include_bytes!("../../../../../../pattern-masks/large/1.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/2.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/3.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/4.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/5.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/6.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/7.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/8.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/9.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/10.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/11.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/12.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/13.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/14.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/15.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/16.mask"),
+    include_bytes!("../../../../../../pattern-masks/large/17.mask"),
+];
 
-struct RenderCache {
-    map: HashMap<u64, String>,  // hash of BoardToPngInput to rendered PNG data URI
-    order: VecDeque<u64>,       // to keep track of order for LRU eviction
-    capacity: usize,
+// the last board rendered at each size, see board_to_png
+static LAST_RENDERS: Lazy<Mutex<HashMap<u32, IncrementalRenderer>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// the largest size board_to_png will render, big enough for a 4K print. masks are only made for
+// the graphic options a board uses, so a big size costs a canvas sized mask per option drawn
+pub const MAX_BOARD_PIXELS: u32 = 4096;
+// how many sizes other than Small and Large keep their masks in memory
+const MAX_CACHED_MASK_SIZES: usize = 2;
+
+// where a MaskSet gets each of its masks from
+enum MaskSource {
+    Baked(&'static [&'static [u8]; GRAPHIC_OPTIONS]),
+    Resized(MaskImages),   // scaled down from a bigger set
+    Rasterised,            // drawn from the pattern mask svg
 }
 
-impl RenderCache {
-    fn new(capacity: usize) -> Self {
-        Self {
-            map: HashMap::new(),
-            order: VecDeque::with_capacity(capacity),
-            capacity,
-        }
+// one mask per graphic option, each covering the whole canvas. each mask is only decoded or
+// generated the first time a tile needs it
+pub struct MaskSet {
+    pixels: u32,
+    source: MaskSource,
+    masks: Vec<OnceCell<Mask>>,
+}
+
+impl MaskSet {
+    fn new(pixels: u32, source: MaskSource) -> Self {
+        Self { pixels, source, masks: (0..GRAPHIC_OPTIONS).map(|_| OnceCell::new()).collect() }
     }
 
-    fn get(&mut self, key: &BoardToPngInput) -> Option<String> {
-        let hash = self.hash_key(key);
-        if let Some(val) = self.map.get(&hash) {
-            // Move the accessed key to the end for LRU
-            self.order.retain(|&k| k != hash);
-            self.order.push_back(hash);
-            Some(val.clone())
-        } else {
-            None
-        }
+    pub fn pixels(&self) -> u32 {
+        self.pixels
     }
 
-    fn insert(&mut self, key: BoardToPngInput, value: String) {
-        let hash = self.hash_key(&key);
-        if self.map.len() >= self.capacity {
-            if let Some(least_used) = self.order.pop_front() {
-                self.map.remove(&least_used);
-            }
-        }
-        self.order.push_back(hash);
-        self.map.insert(hash, value);
+    pub fn is_loaded(&self, option: usize) -> bool {
+        self.masks[option].get().is_some()
     }
 
-    fn hash_key(&self, key: &BoardToPngInput) -> u64 {
-        let mut hasher = DefaultHasher::new();
-        key.board.hash(&mut hasher);
-        key.board_size.hash(&mut hasher);
-        hasher.finish()
+    // the baked masks and the svg are part of the build and checked by the cli_tools tests, so
+    // failing to turn them into a mask is a bug rather than something a caller can handle
+    pub fn get(&self, option: usize) -> &Mask {
+        self.masks[option].get_or_init(|| {
+            let mask = match &self.source {
+                MaskSource::Baked(assets) => decode_mask(assets[option]).expect("Could not decode baked mask"),
+                MaskSource::Resized(source) => {
+                    image::imageops::resize(source.get(option), self.pixels, self.pixels, FilterType::Triangle)
+                }
+                MaskSource::Rasterised => {
+                    rasterise_pattern_mask(option as u8, self.pixels).expect("Could not rasterise pattern mask")
+                }
+            };
+            assert_eq!(mask.dimensions(), (self.pixels, self.pixels), "Mask is the wrong size");
+            mask
+        })
     }
 }
 
-const SMALL_MASKS: [&'static [u8]; GRAPHIC_OPTIONS] = [
-    include_bytes!("../../../../../../pattern-masks/small/1.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/2.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/3.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/4.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/5.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/6.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/7.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/8.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/9.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/10.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/11.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/12.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/13.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/14.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/15.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/16.cache"),
-    include_bytes!("../../../../../../pattern-masks/small/17.cache"),
-];
+pub type MaskImages = Arc<MaskSet>;
 
-const LARGE_MASKS: [&'static [u8]; GRAPHIC_OPTIONS] = [
-    include_bytes!("../../../../../../pattern-masks/large/1.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/2.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/3.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/4.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/5.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/6.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/7.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/8.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/9.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/10.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/11.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/12.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/13.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/14.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/15.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/16.cache"),
-    include_bytes!("../../../../../../pattern-masks/large/17.cache"),
-];
+static SMALL_MASK_IMAGES: Lazy<MaskImages> =
+    Lazy::new(|| Arc::new(MaskSet::new(BoardSize::Small.pixels(), MaskSource::Baked(&SMALL_MASKS))));
+static LARGE_MASK_IMAGES: Lazy<MaskImages> =
+    Lazy::new(|| Arc::new(MaskSet::new(BoardSize::Large.pixels(), MaskSource::Baked(&LARGE_MASKS))));
+// masks for BoardSize::Pixels, least recently used first
+static SIZED_MASK_IMAGES: Lazy<Mutex<VecDeque<(u32, MaskImages)>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+// rendering decodes masks as tiles need them, this decodes every baked mask up front and
+// reports progress as it goes
+#[hdk_extern]
+fn initialize_masks(_: ()) -> ExternResult<()> {
+    let mut progress = 0;
+    for mask_images in [&*SMALL_MASK_IMAGES, &*LARGE_MASK_IMAGES] {
+        for option in 0..GRAPHIC_OPTIONS {
+            mask_images.get(option);
+            progress += 1;
+            let _ = emit_signal(format!("progress: {}", progress));
+        }
+    }
+    Ok(())
+}
+
+// renders the pattern mask for a graphic option from its svg, pixels wide and high
+pub fn rasterise_pattern_mask(option: u8, pixels: u32) -> Result<Mask, String> {
+    let svg_data = Board::generate_pattern_mask(option);
+    let utree = resvg::usvg::Tree::from_str(&svg_data, &resvg::usvg::Options::default())
+        .map_err(|e| format!("Could not parse pattern mask: {:?}", e))?;
+    let rtree = resvg::Tree::from_usvg(&utree);
+    let scale = pixels as f32 / rtree.size.width();
 
-trait ImageBufferExt {
-    fn clear(&mut self);
+    let mut pixmap = Pixmap::new(pixels, pixels).ok_or("Could not create Pixmap")?;
+    rtree.render(Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    let alpha = pixmap.pixels().iter().map(|pixel| pixel.alpha()).collect();
+    ImageBuffer::from_raw(pixels, pixels, alpha).ok_or(String::from("Could not create mask image"))
 }
 
-impl ImageBufferExt for ImageBuffer<Rgba<u8>, Vec<u8>> {
-    fn clear(&mut self) {
-        for pixel in self.pixels_mut() {
-            *pixel = Rgba([0, 0, 0, 0]);
+// sizes up to Large are scaled down from the nearest baked masks that are at least as big, which
+// is quick. anything bigger is rasterised from the svg so it stays sharp
+fn generate_mask_images(pixels: u32) -> MaskSet {
+    let source = if pixels <= BoardSize::Small.pixels() {
+        MaskSource::Resized(SMALL_MASK_IMAGES.clone())
+    } else if pixels <= BoardSize::Large.pixels() {
+        MaskSource::Resized(LARGE_MASK_IMAGES.clone())
+    } else {
+        MaskSource::Rasterised
+    };
+    MaskSet::new(pixels, source)
+}
+
+// the masks for a board size
+pub fn get_mask_images(board_size: &BoardSize) -> Result<MaskImages, String> {
+    let pixels = match board_size {
+        BoardSize::Small => return Ok(SMALL_MASK_IMAGES.clone()),
+        BoardSize::Large => return Ok(LARGE_MASK_IMAGES.clone()),
+        BoardSize::Pixels(pixels) => *pixels,
+    };
+
+    let mut sized_mask_images = SIZED_MASK_IMAGES.lock().unwrap();
+    if let Some(position) = sized_mask_images.iter().position(|(size, _)| *size == pixels) {
+        let entry = sized_mask_images.remove(position).unwrap();
+        sized_mask_images.push_back(entry.clone());
+        return Ok(entry.1);
+    }
+
+    let mask_images: MaskImages = Arc::new(generate_mask_images(pixels));
+    if sized_mask_images.len() >= MAX_CACHED_MASK_SIZES {
+        if let Some((evicted, _)) = sized_mask_images.pop_front() {
+            // the last render at that size is no use without its masks
+            LAST_RENDERS.lock().unwrap().remove(&evicted);
         }
     }
+    sized_mask_images.push_back((pixels, mask_images.clone()));
+    Ok(mask_images)
 }
 
-static INIT_CACHE: Once = Once::new();
-static mut RENDER_CACHE: Option<Mutex<RenderCache>> = None;
-
-fn get_render_cache() -> &'static Mutex<RenderCache> {
-    unsafe {
-        INIT_CACHE.call_once(|| {
-            RENDER_CACHE = Some(Mutex::new(RenderCache::new(100))); // cache capacity of 100
-        });
-        RENDER_CACHE.as_ref().unwrap()
+// fit the longest side of the board into the canvas. tiles are a whole number of pixels, so the
+// image is the biggest multiple of the board that fits and can be up to a tile's worth smaller
+// than the size asked for, e.g. a 40 tile board asked for at 1030 pixels is drawn 1000 pixels wide
+pub fn tile_size_for(board_size: BoardSize, board: &Board) -> Result<u32, String> {
+    let tiles = board.width.max(board.height) as u32;
+    match board_size.pixels() / tiles {
+        0 => Err(format!("Board size must be at least {} pixels to draw {} tiles", tiles, tiles)),
+        tile_size => Ok(tile_size),
     }
 }
 
-static SMALL_MASK_IMAGES: OnceCell<Vec<ImageBuffer<Rgba<u8>, Vec<u8>>>> = OnceCell::new();
-static LARGE_MASK_IMAGES: OnceCell<Vec<ImageBuffer<Rgba<u8>, Vec<u8>>>> = OnceCell::new();
+#[hdk_entry_helper]
+#[derive(Clone, Copy, PartialEq)]
+pub enum BoardSize {
+    Small,         // 600 pixels, from the baked small masks
+    Large,         // 2000 pixels, from the baked large masks
+    Pixels(u32),   // any other size, up to MAX_BOARD_PIXELS in the zome, see tile_size_for
+}
 
-#[hdk_extern]
-fn initialize_masks(_: ()) -> ExternResult<()> {
-    // You can use get_or_init to safely initialize OnceCell
-    let mut progress = 0;
-    let _ = SMALL_MASK_IMAGES.get_or_init(|| {
-        let mut small_images = Vec::with_capacity(GRAPHIC_OPTIONS);
-        for &mask_data in SMALL_MASKS.iter() {
-            // small_images.push(image::load_from_memory(mask_data).unwrap().to_rgba8());
-            let buf = image::ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(600, 600, mask_data.to_vec()).unwrap().convert();
-            small_images.push(buf);
-            debug!("loaded small mask image");
-            progress += 1;
-            let _ = emit_signal(format!("progress: {}", progress));
+impl BoardSize {
+    pub fn pixels(&self) -> u32 {
+        match self {
+            BoardSize::Small => 600,
+            BoardSize::Large => 2000,
+            BoardSize::Pixels(pixels) => *pixels,
         }
-        small_images
-    });
-
-    let _ = LARGE_MASK_IMAGES.get_or_init(|| {
-        let mut large_images = Vec::with_capacity(GRAPHIC_OPTIONS);
-        for &mask_data in LARGE_MASKS.iter() {
-            // large_images.push(image::load_from_memory(mask_data).unwrap().to_rgba8());
-            let buf = image::ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(2000, 2000, mask_data.to_vec()).unwrap().convert();
-            large_images.push(buf);
-            debug!("loaded large mask image");
-            progress += 1;
-            let _ = emit_signal(format!("progress: {}", progress));
-        }
-        large_images
-    });
+    }
 
-    Ok(())
+    // "Small", "Large" or a number of pixels up to max_pixels. the zome keeps to MAX_BOARD_PIXELS,
+    // tools running natively can draw as big as they like
+    pub fn parse_up_to(s: &str, max_pixels: u32) -> Option<Self> {
+        match s {
+            "Small" => Some(BoardSize::Small),
+            "Large" => Some(BoardSize::Large),
+            _ => match s.parse::<u32>() {
+                Ok(600) => Some(BoardSize::Small),
+                Ok(2000) => Some(BoardSize::Large),
+                Ok(pixels) if pixels > 0 && pixels <= max_pixels => Some(BoardSize::Pixels(pixels)),
+                _ => None,
+            },
+        }
+    }
 }
 
+impl FromStr for BoardSize {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BoardSize::parse_up_to(s, MAX_BOARD_PIXELS).ok_or(())
+    }
+}
 
 #[hdk_entry_helper]
-#[derive(PartialEq)]
-pub enum BoardSize {
-    Small = 600,
-    Large = 2000,
+#[derive(Clone, Copy, PartialEq, Hash)]
+pub enum ImageFormat {
+    Png,
+    Webp,   // lossless
+    Svg,    // the board size makes no difference to svgs
 }
 
-impl FromStr for BoardSize {
+impl ImageFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Webp => "image/webp",
+            ImageFormat::Svg => "image/svg+xml",
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "Small" => Ok(BoardSize::Small),
-            "Large" => Ok(BoardSize::Large),
+            "Png" => Ok(ImageFormat::Png),
+            "Webp" => Ok(ImageFormat::Webp),
+            "Svg" => Ok(ImageFormat::Svg),
             _ => Err(()),
         }
     }
@@ -186,121 +285,271 @@ pub struct BoardToPngInput {
     board_size: String,
 }
 
-#[hdk_extern]
-pub fn board_to_png(input: BoardToPngInput) -> ExternResult<String> {
-    let input_for_cache = input.clone();
-    
-    // First, get the cache
-    let mut cache = get_render_cache().lock().unwrap();
-
-    // Check if image is in cache
-    if let Some(data_uri) = cache.get(&input) {
-        return Ok(data_uri);
-    }
+#[hdk_entry_helper]
+#[derive(Clone)]
+pub struct BoardToImageInput {
+    pub board: BoardInput,
+    pub board_size: String,
+    pub format: String,
+}
 
-    // Try to get the result from the cache first.
-    if let Some(cached_result) = cache.get(&input) {
-        return Ok(cached_result);
+// an encoded image, for clients that write files or serve it rather than showing a data uri
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct BoardImage {
+    pub bytes: Vec<u8>,
+    pub content_type: String,
+}
+
+pub fn parse_board_size(board_size: &str) -> ExternResult<BoardSize> {
+    board_size.parse::<BoardSize>().map_err(|_| {
+        wasm_error!(format!("Board size must be Small, Large or a number of pixels up to {}", MAX_BOARD_PIXELS))
+    })
+}
+
+pub fn parse_image_format(format: &str) -> ExternResult<ImageFormat> {
+    format.parse::<ImageFormat>().map_err(|_| wasm_error!("Image format must be Png, Webp or Svg"))
+}
+
+// the render cache key, the same for every way of asking for the same picture
+fn cache_key(board: &Board, board_size: BoardSize, format: ImageFormat) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    // to_bytes draws black tiles and empty ones the same, the checkpoint bytes tell them apart
+    (board.width, board.height, board.to_checkpoint_bytes()).hash(&mut hasher);
+    match format {
+        ImageFormat::Svg => 0u32.hash(&mut hasher),
+        _ => board_size.pixels().hash(&mut hasher),
     }
+    format.hash(&mut hasher);
+    hasher.finish()
+}
 
-    let board_size = input.board_size.parse::<BoardSize>().map_err(|_| {
-        wasm_error!("Invalid board size provided")
-    })?;
-    let board = Board::from_board_input(input.board).map_err(|e| wasm_error!(e))?;
+// draws the board at a size, only redrawing the tiles that changed since the last board drawn at it
+fn render_canvas<T>(
+    board: Board,
+    board_size: BoardSize,
+    use_canvas: impl FnOnce(&ImageBuffer<Rgba<u8>, Vec<u8>>) -> Result<T, String>,
+) -> Result<T, String> {
+    let mask_images = get_mask_images(&board_size)?;
+    let tile_size = tile_size_for(board_size, &board)?;
+
+    // boards asked for one after another usually differ by a move or two, so only redraw those tiles
+    let mut last_renders = LAST_RENDERS.lock().unwrap();
+    let renderer = match last_renders.entry(board_size.pixels()) {
+        Entry::Occupied(entry) => {
+            let renderer = entry.into_mut();
+            renderer.render_board(board, &mask_images, tile_size);
+            renderer
+        }
+        Entry::Vacant(entry) => entry.insert(IncrementalRenderer::new(board, &mask_images, tile_size)),
+    };
+    use_canvas(renderer.canvas())
+}
 
-    // Ensure masks are initialized
-    let _ = initialize_masks(());
+fn encode_canvas(canvas: &ImageBuffer<Rgba<u8>, Vec<u8>>, format: ImageFormat) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    match format {
+        ImageFormat::Png => PngEncoder::new(&mut bytes)
+            .encode(canvas, canvas.width(), canvas.height(), image::ColorType::Rgba8)
+            .map_err(|e| e.to_string())?,
+        ImageFormat::Webp => image_webp::WebPEncoder::new(&mut bytes)
+            .encode(canvas, canvas.width(), canvas.height(), image_webp::ColorType::Rgba8)
+            .map_err(|e| e.to_string())?,
+        ImageFormat::Svg => return Err(String::from("Svgs are not drawn on a canvas")),
+    }
+    Ok(bytes)
+}
 
-    // Use the preloaded images
-    let mask_images = if board_size == BoardSize::Small {
-        SMALL_MASK_IMAGES.get().expect("Masks not initialized")
-    } else {
-        LARGE_MASK_IMAGES.get().expect("Masks not initialized")
+// the encoded image of a board, from the render cache when it has already been made
+pub fn board_image_bytes(board: Board, board_size: BoardSize, format: ImageFormat) -> ExternResult<Vec<u8>> {
+    let cache_key = cache_key(&board, board_size, format);
+    if let Some(bytes) = RENDER_CACHE.lock().unwrap().get(cache_key) {
+        return Ok(bytes.clone());
+    }
+
+    let bytes = match format {
+        ImageFormat::Svg => board.generate_svg_with_defs().into_bytes(),
+        _ => render_canvas(board, board_size, |canvas| encode_canvas(canvas, format))
+            .map_err(|e| wasm_error!(e))?,
     };
-    
-    let tile_size = board_size as u32 / BOARD_SIZE as u32;
- 
-    let img_buffer = draw_board(board, &mask_images[..], tile_size as u32);
+    RENDER_CACHE.lock().unwrap().insert(cache_key, bytes.clone(), bytes.len());
+    Ok(bytes)
+}
 
-    let mut buffer = Cursor::new(Vec::new());
-    let encoder = PngEncoder::new(&mut buffer);
-    encoder.encode(&img_buffer, img_buffer.width(), img_buffer.height(), image::ColorType::Rgba8).unwrap();
-    
-    // base64 encode the buffer into a datauri for bmp
-    let bytes = buffer.into_inner();
-    let data_uri = format!("data:image/png;base64,{}", base64::encode(bytes));
+pub fn board_image(board: Board, board_size: BoardSize, format: ImageFormat) -> ExternResult<BoardImage> {
+    Ok(BoardImage {
+        bytes: board_image_bytes(board, board_size, format)?,
+        content_type: format.content_type().to_string(),
+    })
+}
 
-    // Insert the result into the cache before returning.
-    cache.insert(input_for_cache, data_uri.clone());
+#[hdk_extern]
+pub fn board_to_png(input: BoardToPngInput) -> ExternResult<String> {
+    let board_size = parse_board_size(&input.board_size)?;
+    let board = Board::from_board_input(input.board).map_err(|e| wasm_error!(e))?;
+    let bytes = board_image_bytes(board, board_size, ImageFormat::Png)?;
+    Ok(format!("data:image/png;base64,{}", STANDARD.encode(bytes)))
+}
 
-    Ok(data_uri)
+#[hdk_extern]
+pub fn board_to_image(input: BoardToImageInput) -> ExternResult<BoardImage> {
+    let board_size = parse_board_size(&input.board_size)?;
+    let format = parse_image_format(&input.format)?;
+    let board = Board::from_board_input(input.board).map_err(|e| wasm_error!(e))?;
+    board_image(board, board_size, format)
 }
 
-fn draw_board(board: Board, mask_images: &[ImageBuffer<Rgba<u8>, Vec<u8>>], tile_size: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-    let mut canvas = ImageBuffer::new(BOARD_SIZE as u32 * tile_size, BOARD_SIZE as u32 * tile_size);
+pub fn draw_board(board: Board, mask_images: &MaskSet, tile_size: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let (across, down) = board.canvas_tiles();
+    let mut canvas = ImageBuffer::new(across as u32 * tile_size, down as u32 * tile_size);
     // Fill the canvas with white
     canvas.pixels_mut().for_each(|p| *p = Rgba([255, 255, 255, 255]));
 
-    for (x, row) in board.tiles.iter().enumerate() {
-        for (y, tile) in row.iter().enumerate() {
-            if let Some(color) = &tile.color {
-                if let Some(graphic_option) = tile.graphic_option {
-                    match graphic_option {
-                        0..=16 => {
-                            let mask = &mask_images[graphic_option as usize];
-                            for i in 0..tile_size {
-                                for j in 0..tile_size {
-                                    let px = x as u32 * tile_size + i;
-                                    let py = y as u32 * tile_size + j;
-                                    let mask_pixel = mask.get_pixel(px, py);
-                                    if mask_pixel[3] != 0 {  // non-alpha
-                                        let fill = Rgba([color.r, color.g, color.b, mask_pixel[3]]);
-                                        canvas.put_pixel(px, py, fill);
-                                    }
-                                }
-                            }
-                        },
-                        17..=33 => {
-                            let mask = &mask_images[graphic_option as usize % 17];
-                            for i in 0..tile_size {
-                                for j in 0..tile_size {
-                                    let px = x as u32 * tile_size + i;
-                                    let py = y as u32 * tile_size + j;
-
-                                    let mask_pixel = mask.get_pixel(px, py);
-                                    if mask_pixel[3] != 0 {  // non-alpha
-                                        let fill = Rgba([color.r, color.g, color.b, 255]);
-                                        
-                                        // Blend the mask pixel with the fill color
-                                        let blended_r = (mask_pixel[0] as f32 * mask_pixel[3] as f32 / 255.0 + fill[0] as f32 * (1.0 - mask_pixel[3] as f32 / 255.0)) as u8;
-                                        let blended_g = (mask_pixel[1] as f32 * mask_pixel[3] as f32 / 255.0 + fill[1] as f32 * (1.0 - mask_pixel[3] as f32 / 255.0)) as u8;
-                                        let blended_b = (mask_pixel[2] as f32 * mask_pixel[3] as f32 / 255.0 + fill[2] as f32 * (1.0 - mask_pixel[3] as f32 / 255.0)) as u8;
-                                        let blended_pixel = Rgba([blended_r, blended_g, blended_b, 255]);
-                                        
-                                        canvas.put_pixel(px, py, blended_pixel);
-                                    } else {
-                                        let fill = Rgba([color.r, color.g, color.b, 255]);
-                                        canvas.put_pixel(px, py, fill);
-                                    }
-                                }
-                            }
-                        },
-                        34 => {
-                            let fill = Rgba([color.r, color.g, color.b, 255]);
-                            for i in 0..tile_size {
-                                for j in 0..tile_size {
-                                    // debug!("drawing graphic option 35 pixel at {}, {}", x, y);
-                                    let px = x as u32 * tile_size + i;
-                                    let py = y as u32 * tile_size + j;
-                                    canvas.put_pixel(px, py, fill);
-                                }
-                            }
-                        },
-                        _ => {}
-                    }
+    for (left, top, tile) in board.tiles_on_canvas() {
+        draw_tile(&mut canvas, left as u32, top as u32, tile, mask_images, tile_size);
+    }
+    canvas
+}
+
+// the top colour laid over the bottom one with the given alpha, rounded to the nearest value
+fn blend(top: [u8; 3], bottom: [u8; 3], alpha: u8) -> Rgba<u8> {
+    let channel = |top: u8, bottom: u8| {
+        ((top as u32 * alpha as u32 + bottom as u32 * (255 - alpha as u32) + 127) / 255) as u8
+    };
+    Rgba([channel(top[0], bottom[0]), channel(top[1], bottom[1]), channel(top[2], bottom[2]), 255])
+}
+
+// draws a tile onto a canvas that is white underneath it, the same way Board::generate_svg_document does.
+// left and top are in tiles, see Board::canvas_position
+fn draw_tile(canvas: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, left: u32, top: u32, tile: &Tile, mask_images: &MaskSet, tile_size: u32) {
+    const WHITE: [u8; 3] = [255, 255, 255];
+    let pixels = (0..tile_size).flat_map(|i| (0..tile_size).map(move |j| (left * tile_size + i, top * tile_size + j)));
+
+    match tile.graphic() {
+        TileGraphic::Empty => {}
+        TileGraphic::Pattern { color, mask } => {
+            // the masks hold the pattern's alpha
+            let mask = mask_images.get(mask);
+            for (px, py) in pixels {
+                let alpha = mask.get_pixel(px, py)[0];
+                canvas.put_pixel(px, py, blend([color.r, color.g, color.b], WHITE, alpha));
+            }
+        }
+        TileGraphic::Inverse { color, mask } => {
+            let mask = mask_images.get(mask);
+            for (px, py) in pixels {
+                let alpha = mask.get_pixel(px, py)[0];
+                canvas.put_pixel(px, py, blend(WHITE, [color.r, color.g, color.b], alpha));
+            }
+        }
+        TileGraphic::Solid { color } => {
+            for (px, py) in pixels {
+                canvas.put_pixel(px, py, Rgba([color.r, color.g, color.b, 255]));
+            }
+        }
+    }
+}
+
+// paints a tile's area white again so the tile can be redrawn
+fn clear_tile(canvas: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, left: u32, top: u32, tile_size: u32) {
+    for i in 0..tile_size {
+        for j in 0..tile_size {
+            canvas.put_pixel(left * tile_size + i, top * tile_size + j, Rgba([255, 255, 255, 255]));
+        }
+    }
+}
+
+// keeps the last rendered board and its canvas, so the next board or move only redraws the tiles
+// that changed
+pub struct IncrementalRenderer {
+    board: Board,
+    canvas: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    tile_size: u32,
+}
+
+impl IncrementalRenderer {
+    pub fn new(board: Board, mask_images: &MaskSet, tile_size: u32) -> Self {
+        let canvas = draw_board(board.clone(), mask_images, tile_size);
+        Self { board, canvas, tile_size }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn canvas(&self) -> &ImageBuffer<Rgba<u8>, Vec<u8>> {
+        &self.canvas
+    }
+
+    fn redraw_tile(&mut self, row: usize, column: usize, mask_images: &MaskSet) {
+        let tile = self.board.tiles[row][column];
+        let (left, top) = Board::canvas_position(row, column);
+        clear_tile(&mut self.canvas, left as u32, top as u32, self.tile_size);
+        draw_tile(&mut self.canvas, left as u32, top as u32, &tile, mask_images, self.tile_size);
+    }
+
+    pub fn apply_game_move(&mut self, game_move: &GameMove, mask_images: &MaskSet) {
+        for change in apply_game_move_with_changes(&mut self.board, game_move) {
+            self.redraw_tile(change.y as usize, change.x as usize, mask_images);
+        }
+    }
+
+    // brings the canvas up to date with any board, redrawing everything if its dimensions differ
+    pub fn render_board(&mut self, board: Board, mask_images: &MaskSet, tile_size: u32) {
+        if board.width != self.board.width || board.height != self.board.height || tile_size != self.tile_size {
+            *self = Self::new(board, mask_images, tile_size);
+            return;
+        }
+        let previous_board = std::mem::replace(&mut self.board, board);
+        for row in 0..self.board.height {
+            for column in 0..self.board.width {
+                if self.board.tiles[row][column] != previous_board.tiles[row][column] {
+                    self.redraw_tile(row, column, mask_images);
                 }
             }
         }
     }
-    canvas
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_board_sizes() {
+        assert!(matches!("2000".parse::<BoardSize>(), Ok(BoardSize::Large)));
+        assert!(matches!("64".parse::<BoardSize>(), Ok(BoardSize::Pixels(64))));
+        assert!(matches!("3840".parse::<BoardSize>(), Ok(BoardSize::Pixels(3840))));
+        assert!(matches!(MAX_BOARD_PIXELS.to_string().parse::<BoardSize>(), Ok(BoardSize::Pixels(MAX_BOARD_PIXELS))));
+        assert!((MAX_BOARD_PIXELS + 1).to_string().parse::<BoardSize>().is_err());
+        assert!("0".parse::<BoardSize>().is_err());
+        assert!(matches!(BoardSize::parse_up_to("8000", u32::MAX), Some(BoardSize::Pixels(8000))));
+    }
+
+    #[test]
+    fn sized_masks_are_scaled_from_the_baked_masks() {
+        let mask_images = get_mask_images(&BoardSize::Pixels(1999)).unwrap();
+        assert!(matches!(mask_images.source, MaskSource::Resized(ref source) if source.pixels() == BoardSize::Large.pixels()));
+        let mask_images = get_mask_images(&BoardSize::Pixels(120)).unwrap();
+        assert!(matches!(mask_images.source, MaskSource::Resized(ref source) if source.pixels() == BoardSize::Small.pixels()));
+    }
+
+    #[test]
+    fn rasterises_only_the_masks_a_board_uses_above_large() {
+        let mask_images = get_mask_images(&BoardSize::Pixels(2400)).unwrap();
+        assert!(matches!(mask_images.source, MaskSource::Rasterised));
+        assert!((0..GRAPHIC_OPTIONS).all(|option| !mask_images.is_loaded(option)));
+
+        assert_eq!(mask_images.get(3).dimensions(), (2400, 2400));
+        assert!(mask_images.is_loaded(3));
+        assert_eq!((0..GRAPHIC_OPTIONS).filter(|option| mask_images.is_loaded(*option)).count(), 1);
+    }
+
+    #[test]
+    fn tile_sizes_round_down_to_whole_pixels() {
+        let board = Board::reconstruct_from_game_moves(40, 30, &[]);
+        assert_eq!(tile_size_for(BoardSize::Pixels(1000), &board), Ok(25));
+        assert_eq!(tile_size_for(BoardSize::Pixels(1039), &board), Ok(25));
+        assert!(tile_size_for(BoardSize::Pixels(39), &board).is_err());
+    }
+}
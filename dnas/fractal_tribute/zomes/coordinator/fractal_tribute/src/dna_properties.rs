@@ -1,7 +1,13 @@
 use hdk::prelude::*;
-use fractal_tribute_integrity::dna_properties::{DnaProperties, _get_dna_properties};
+use fractal_tribute_integrity::dna_properties::{DnaProperties, GamePhase, _get_dna_properties, game_phase_at};
 
 #[hdk_extern]
 pub fn get_dna_properties(_:()) -> ExternResult<DnaProperties> {
    _get_dna_properties(())
 }
+
+#[hdk_extern]
+pub fn get_game_phase(_:()) -> ExternResult<GamePhase> {
+   let now = sys_time()?.as_seconds_and_nanos().0;
+   Ok(game_phase_at(&_get_dna_properties(())?, now))
+}
@@ -1,8 +1,21 @@
 use hdk::prelude::*;
 use fractal_tribute_integrity::*;
+use fractal_tribute_integrity::dna_properties::_get_dna_properties;
+use ethers_core::types::transaction::eip712::{EIP712Domain, Eip712};
+use ethers_core::types::H160;
+use ethers_core::utils::hex;
 
 #[hdk_extern]
 pub fn create_evm_key_binding(evm_key_binding: EvmKeyBinding) -> ExternResult<Record> {
+    let agent_pubkey = agent_info()?.agent_latest_pubkey;
+    // validation can only stop a second binding on this agent's own chain, so check the index
+    // for other agents first
+    if let Some(agent) = evm_address_claimant(&evm_key_binding.evm_key)? {
+        if agent != agent_pubkey {
+            return Err(wasm_error!("This EVM key is already bound to another agent"));
+        }
+    }
+
     let evm_key_binding_hash = create_entry(
         &EntryTypes::EvmKeyBinding(evm_key_binding.clone()),
     )?;
@@ -14,11 +27,206 @@ pub fn create_evm_key_binding(evm_key_binding: EvmKeyBinding) -> ExternResult<Re
         )?;
 
     // create a link from the agent's pubkey to the evm key binding
+    create_link(agent_pubkey.clone(), evm_key_binding_hash.clone(), LinkTypes::AgentToEvmKeyBinding, ())?;
+    // and from the evm address back to the agent
+    create_link(
+        evm_address_link_base(&evm_key_binding.evm_key)?,
+        agent_pubkey,
+        LinkTypes::EvmAddressToAgent,
+        LinkTag::new(evm_key_binding_hash.get_raw_39().to_vec()),
+    )?;
+    Ok(record)
+}
+
+// oldest first, ties broken by hash, so every agent resolves the same links the same way
+fn earliest_first(mut links: Vec<Link>) -> Vec<Link> {
+    links.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.create_link_hash.cmp(&b.create_link_hash)));
+    links
+}
+
+// an eth_signTypedData_v4 request, wallets show the domain and message fields as text
+fn typed_data(domain: &EIP712Domain, primary_type: &str, fields: serde_json::Value, message: serde_json::Value) -> serde_json::Value {
+    let EIP712Domain { name, version, chain_id, verifying_contract, salt } = domain;
+    serde_json::json!({
+        "types": {
+            "EIP712Domain": [
+                { "name": "name", "type": "string" },
+                { "name": "version", "type": "string" },
+                { "name": "chainId", "type": "uint256" },
+                { "name": "verifyingContract", "type": "address" },
+                { "name": "salt", "type": "bytes32" },
+            ],
+            primary_type: fields,
+        },
+        "primaryType": primary_type,
+        "domain": {
+            "name": name,
+            "version": version,
+            "chainId": chain_id.map(|chain_id| chain_id.as_u64()),
+            "verifyingContract": verifying_contract.map(|address| format!("{:?}", address)),
+            "salt": salt.map(|salt| format!("0x{}", hex::encode(salt))),
+        },
+        "message": message,
+    })
+}
+
+pub fn evm_key_binding_typed_data(message: &BindHolochainAgent) -> serde_json::Value {
+    typed_data(
+        &message.domain,
+        "BindHolochainAgent",
+        serde_json::json!([{ "name": "agent", "type": "string" }]),
+        serde_json::json!({ "agent": message.agent }),
+    )
+}
+
+pub fn evm_key_rotation_typed_data(message: &RotateEvmKey) -> serde_json::Value {
+    typed_data(
+        &message.domain,
+        "RotateEvmKey",
+        serde_json::json!([
+            { "name": "agent", "type": "string" },
+            { "name": "previousKey", "type": "address" },
+            { "name": "newKey", "type": "address" },
+        ]),
+        serde_json::json!({
+            "agent": message.agent,
+            "previousKey": format!("{:?}", message.previous_key),
+            "newKey": format!("{:?}", message.new_key),
+        }),
+    )
+}
+
+pub fn contract_wallet_attestation_typed_data(message: &AttestContractWallet) -> serde_json::Value {
+    typed_data(
+        &message.domain,
+        "AttestContractWallet",
+        serde_json::json!([
+            { "name": "agent", "type": "string" },
+            { "name": "wallet", "type": "address" },
+            { "name": "messageHash", "type": "bytes32" },
+            { "name": "signatureHash", "type": "bytes32" },
+        ]),
+        serde_json::json!({
+            "agent": message.agent,
+            "wallet": format!("{:?}", message.wallet),
+            "messageHash": format!("0x{}", hex::encode(message.message_hash)),
+            "signatureHash": format!("0x{}", hex::encode(message.signature_hash)),
+        }),
+    )
+}
+
+fn this_dna_evm_key_binding_domain() -> ExternResult<EIP712Domain> {
+    evm_key_binding_domain(&_get_dna_properties(())?, &dna_info()?.hash).map_err(|e| wasm_error!(e))
+}
+
+// what this agent's wallet should sign to make an Eip712 binding, as json for signTypedData
+#[hdk_extern]
+pub fn get_evm_key_binding_typed_data(_: ()) -> ExternResult<String> {
+    let message = BindHolochainAgent::new(&agent_info()?.agent_latest_pubkey, this_dna_evm_key_binding_domain()?);
+    // fails early rather than asking the wallet to sign something validation can't check
+    message.encode_eip712().map_err(|e| wasm_error!(e.to_string()))?;
+    Ok(evm_key_binding_typed_data(&message).to_string())
+}
+
+#[hdk_entry_helper]
+pub struct ContractWalletAttestationInput {
+    pub agent: AgentPubKey,
+    pub evm_key_binding: EvmKeyBinding,   // a ContractWallet binding, before it has its attestation
+}
+
+// what the contract wallet verifier signs once the wallet's isValidSignature has accepted the
+// binding's signature for messageHash
+#[hdk_extern]
+pub fn get_contract_wallet_attestation_typed_data(input: ContractWalletAttestationInput) -> ExternResult<String> {
+    let message = AttestContractWallet::new(&input.agent, &input.evm_key_binding, this_dna_evm_key_binding_domain()?)
+        .map_err(|e| wasm_error!(e))?;
+    Ok(contract_wallet_attestation_typed_data(&message).to_string())
+}
+
+// what the current key, or the game master, signs to hand this agent over to new_evm_key
+#[hdk_extern]
+pub fn get_evm_key_rotation_typed_data(new_evm_key: Vec<u8>) -> ExternResult<String> {
+    let (_, current) = my_active_evm_key_binding()?.ok_or(wasm_error!("No EvmKeyBinding found for this agent"))?;
+    let address = |evm_key: &[u8]| -> ExternResult<H160> {
+        let address: [u8; 20] = evm_key.try_into().map_err(|_| wasm_error!("EVM key must be 20 bytes"))?;
+        Ok(H160::from(address))
+    };
+    let message = RotateEvmKey::new(
+        &agent_info()?.agent_latest_pubkey,
+        address(&current.evm_key)?,
+        address(&new_evm_key)?,
+        this_dna_evm_key_binding_domain()?,
+    );
+    Ok(evm_key_rotation_typed_data(&message).to_string())
+}
+
+// moves this agent to a new key. the binding is signed by the new key and carries a handover
+// signed by the current key or the game master, see validate_update_evm_key_binding
+#[hdk_extern]
+pub fn rotate_evm_key_binding(evm_key_binding: EvmKeyBinding) -> ExternResult<Record> {
     let agent_pubkey = agent_info()?.agent_latest_pubkey;
-    create_link(agent_pubkey, evm_key_binding_hash.clone(), LinkTypes::AgentToEvmKeyBinding, ())?;
+    let (current_hash, _) = my_active_evm_key_binding()?.ok_or(wasm_error!("No EvmKeyBinding found for this agent"))?;
+    if let Some(agent) = evm_address_claimant(&evm_key_binding.evm_key)? {
+        if agent != agent_pubkey {
+            return Err(wasm_error!("This EVM key is already bound to another agent"));
+        }
+    }
+
+    let evm_key_binding_hash = update_entry(current_hash, &EntryTypes::EvmKeyBinding(evm_key_binding.clone()))?;
+    let record = get(evm_key_binding_hash.clone(), GetOptions::default())?
+        .ok_or(
+            wasm_error!(
+                WasmErrorInner::Guest(String::from("Could not find the rotated EvmKeyBinding"))
+            ),
+        )?;
+    create_link(
+        evm_address_link_base(&evm_key_binding.evm_key)?,
+        agent_pubkey,
+        LinkTypes::EvmAddressToAgent,
+        LinkTag::new(evm_key_binding_hash.get_raw_39().to_vec()),
+    )?;
     Ok(record)
 }
 
+fn evm_key_binding_from_record(record: &Record) -> ExternResult<EvmKeyBinding> {
+    record.entry().to_app_option().map_err(|e| wasm_error!(e))?
+        .ok_or(
+            wasm_error!(
+                WasmErrorInner::Guest(String::from("EvmKeyBinding not found at link target"))
+            ),
+        )
+}
+
+// this agent's binding at the head of their own chain, the same one validation resolves for
+// the links they make next
+fn my_active_evm_key_binding() -> ExternResult<Option<(ActionHash, EvmKeyBinding)>> {
+    let filter = ChainQueryFilter::new()
+        .entry_type(UnitEntryTypes::EvmKeyBinding.try_into()?)
+        .include_entries(true);
+    match query(filter)?.into_iter().max_by_key(|record| record.action().action_seq()) {
+        Some(record) => Ok(Some((record.action_address().clone(), evm_key_binding_from_record(&record)?))),
+        None => Ok(None),
+    }
+}
+
+// follows a binding through the agent's rotations to the one in force now. validation only
+// lets an agent rotate their current binding, so each has at most one valid update
+fn latest_rotation(action_hash: ActionHash, agent: &AgentPubKey) -> ExternResult<Option<Record>> {
+    let mut latest = None;
+    let mut next = Some(action_hash);
+    while let Some(action_hash) = next.take() {
+        let Some(Details::Record(details)) = get_details(action_hash, GetOptions::default())? else { break };
+        next = details
+            .updates
+            .iter()
+            .filter(|update| update.hashed.content.author() == agent)
+            .min_by_key(|update| (update.hashed.content.timestamp(), update.hashed.hash.clone()))
+            .map(|update| update.hashed.hash.clone());
+        latest = Some(details.record);
+    }
+    Ok(latest)
+}
+
 #[derive(Debug)]
 pub enum EvmAddressError {
     QueryError,
@@ -31,33 +239,113 @@ pub enum EvmAddressError {
 #[hdk_extern]
 pub fn get_evm_address(_:()) -> ExternResult<Vec<u8>> {
     let agent_pubkey = agent_info()?.agent_latest_pubkey;
-    let evm_address = get_agent_evm_address(agent_pubkey)?;
-    Ok(evm_address)
+    match my_active_evm_key_binding()? {
+        Some((_, evm_key_binding)) => check_evm_address_is_agents(evm_key_binding.evm_key, &agent_pubkey),
+        None => Err(wasm_error!("No EvmKeyBinding found for this agent")),
+    }
 }
 
+// an address that another agent claimed first isn't theirs. bindings made before the index
+// have no EvmAddressToAgent link
+fn check_evm_address_is_agents(evm_key: Vec<u8>, agent: &AgentPubKey) -> ExternResult<Vec<u8>> {
+    match evm_address_claimant(&evm_key)? {
+        Some(bound_agent) if &bound_agent != agent => Err(wasm_error!("This agent's EVM key is bound to another agent")),
+        _ => Ok(evm_key),
+    }
+}
+
+// the address an agent is bound to now, after any rotations
 #[hdk_extern]
 pub fn get_agent_evm_address(base: AgentPubKey) -> ExternResult<Vec<u8>> {
-    let links = get_links(base, LinkTypes::AgentToEvmKeyBinding, None)?;
-        let get_input: Vec<GetInput> = links
-        .into_iter()
-        .map(|link| GetInput::new(
-            ActionHash::from(link.target).into(),
-            GetOptions::default(),
-        ))
-        .collect();
-    let records: Vec<Record> = HDK
-        .with(|hdk| hdk.borrow().get(get_input))?
+    let links = get_links(base.clone(), LinkTypes::AgentToEvmKeyBinding, None)?;
+    for link in earliest_first(links) {
+        let Some(action_hash) = link.target.into_action_hash() else { continue };
+        let Some(record) = latest_rotation(action_hash, &base)? else { continue };
+        if record.action().author() != &base {
+            continue;
+        }
+        return check_evm_address_is_agents(evm_key_binding_from_record(&record)?.evm_key, &base);
+    }
+    Err(wasm_error!("No EvmKeyBinding found for this agent"))
+}
+
+// the earliest EvmAddressToAgent link for an address, the claim that owns it
+fn earliest_evm_address_claim(evm_key: &[u8]) -> ExternResult<Option<(AgentPubKey, Link)>> {
+    let links = get_links(evm_address_link_base(evm_key)?, LinkTypes::EvmAddressToAgent, None)?;
+    Ok(earliest_first(links)
         .into_iter()
-        .filter_map(|r| r)
-        .collect();
-    if records.len() == 0 {
-        return Err(wasm_error!("No EvmKeyBinding found for this agent"));
+        .find_map(|link| link.target.clone().into_entry_hash().map(|agent| (AgentPubKey::from(agent), link))))
+}
+
+// the agent who claimed an address first. an agent that has rotated away from the address keeps
+// the claim, so it can't be bound again
+fn evm_address_claimant(evm_key: &[u8]) -> ExternResult<Option<AgentPubKey>> {
+    Ok(earliest_evm_address_claim(evm_key)?.map(|(agent, _)| agent))
+}
+
+// the agent an evm address is bound to now, the earliest to claim it if more than one tried. once
+// that agent has rotated to another key the address no longer resolves to anyone
+#[hdk_extern]
+pub fn get_agent_for_evm_address(evm_key: Vec<u8>) -> ExternResult<Option<AgentPubKey>> {
+    let Some((agent, link)) = earliest_evm_address_claim(&evm_key)? else { return Ok(None) };
+    let Ok(binding_hash) = ActionHash::from_raw_39(link.tag.into_inner()) else { return Ok(None) };
+    match latest_rotation(binding_hash.clone(), &agent)? {
+        Some(record) if record.action_address() == &binding_hash => Ok(Some(agent)),
+        _ => Ok(None),
     }
-    let evm_key_binding: EvmKeyBinding = records[0].entry().to_app_option().map_err(|e| wasm_error!(e))?
-        .ok_or(
-            wasm_error!(
-                WasmErrorInner::Guest(String::from("EvmKeyBinding not found at link target"))
-            ),
-        )?;
-    Ok(evm_key_binding.evm_key)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers_core::types::transaction::eip712::TypedData;
+
+    fn domain() -> EIP712Domain {
+        EIP712Domain {
+            name: Some(EIP712_DOMAIN_NAME.to_string()),
+            version: Some(EIP712_DOMAIN_VERSION.to_string()),
+            chain_id: Some(137.into()),
+            verifying_contract: Some("0x5FbDB2315678afecb367f032d93F642f64180aa3".parse().unwrap()),
+            salt: Some([9; 32]),
+        }
+    }
+
+    // ethers reads a salt as an array of bytes rather than the hex wallets take
+    fn ethers_digest(mut json: serde_json::Value) -> [u8; 32] {
+        assert_eq!(json["domain"]["salt"], format!("0x{}", "09".repeat(32)));
+        json["domain"]["salt"] = serde_json::json!([9u8; 32].to_vec());
+        let typed_data: TypedData = serde_json::from_value(json).unwrap();
+        typed_data.encode_eip712().unwrap()
+    }
+
+    #[test]
+    fn typed_data_json_signs_the_message_validation_checks() {
+        let message = BindHolochainAgent::new(&AgentPubKey::from_raw_36(vec![7; 36]), domain());
+        let json = evm_key_binding_typed_data(&message);
+        assert!(json["message"]["agent"].as_str().unwrap().starts_with("uhCAk"));
+        assert_eq!(ethers_digest(json), message.encode_eip712().unwrap());
+    }
+
+    #[test]
+    fn attestation_typed_data_json_signs_what_validation_checks() {
+        let evm_key_binding = EvmKeyBinding {
+            evm_key: vec![3; 20],
+            signature_bytes: vec![4; 130],
+            signature_scheme: EvmSignatureScheme::ContractWallet,
+            handover: None,
+            attestation: None,
+        };
+        let message = AttestContractWallet::new(&AgentPubKey::from_raw_36(vec![7; 36]), &evm_key_binding, domain()).unwrap();
+        let json = contract_wallet_attestation_typed_data(&message);
+        assert_eq!(json["message"]["wallet"], format!("0x{}", "03".repeat(20)));
+        assert_eq!(ethers_digest(json), message.encode_eip712().unwrap());
+    }
+
+    #[test]
+    fn rotation_typed_data_json_signs_the_handover_validation_checks() {
+        let message = RotateEvmKey::new(&AgentPubKey::from_raw_36(vec![7; 36]), H160::from([1; 20]), H160::from([2; 20]), domain());
+        let json = evm_key_rotation_typed_data(&message);
+        assert_eq!(json["message"]["newKey"], format!("0x{}", "02".repeat(20)));
+        assert_eq!(ethers_digest(json), message.encode_eip712().unwrap());
+    }
+}
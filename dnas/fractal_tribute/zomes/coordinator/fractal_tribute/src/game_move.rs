@@ -1,14 +1,14 @@
 use hdk::prelude::*;
 use fractal_tribute_integrity::*;
-use crate::{evm_key_binding::get_evm_address, dna_properties::get_dna_properties};
+use fractal_tribute_integrity::dna_properties::GamePhaseKind;
+use crate::{evm_key_binding::get_evm_address, dna_properties::get_game_phase};
 
 #[hdk_extern]
 pub fn create_game_move(game_move_bytes: Vec<u8>) -> ExternResult<Record> {
 
-    let game_end_time = get_dna_properties(())?.game_end_time;
-    let now = sys_time()?.as_seconds_and_nanos().0;
-    if now > game_end_time.into() {
-        return Err(wasm_error!("Game has ended"));
+    let phase = get_game_phase(())?;
+    if phase.kind != GamePhaseKind::OpenPlay {
+        return Err(wasm_error!(format!("Game moves are not allowed in the {:?} phase", phase.kind)));
     }
 
     let game_move_bytes_slice = game_move_bytes.as_slice();
@@ -24,8 +24,8 @@ pub fn create_game_move(game_move_bytes: Vec<u8>) -> ExternResult<Record> {
         return Err(wasm_error!("Must change at least one pixel"));
     }
     
-    if game_move.count_changes() > 20 {
-        return Err(wasm_error!("Max 20 changes allowed"));
+    if game_move.count_changes() > MAX_PIXEL_CHANGES {
+        return Err(wasm_error!(format!("Max {} changes allowed", MAX_PIXEL_CHANGES)));
     }
     
     let game_move_hash = create_entry(&EntryTypes::GameMove(game_move.clone()))?;
@@ -36,8 +36,11 @@ pub fn create_game_move(game_move_bytes: Vec<u8>) -> ExternResult<Record> {
             ),
         )?;
 
-    let path = Path::from("all_game_moves");
-    create_link(path.path_entry_hash()?, game_move_hash.clone(), LinkTypes::AllGameMoves, game_move_bytes)?;
+    // link the move under the time bucket it was created in
+    let path = game_move_bucket_path(game_move_bucket(_record.action().timestamp()));
+    path.clone().typed(LinkTypes::GameMoveBuckets)?.ensure()?;
+    // the tag is always written in the current encoding, whichever one the client sent
+    create_link(path.path_entry_hash()?, game_move_hash.clone(), LinkTypes::AllGameMoves, game_move.to_bytes())?;
 
     Ok(_record)
 }
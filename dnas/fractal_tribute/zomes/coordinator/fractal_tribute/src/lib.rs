@@ -1,11 +1,17 @@
 pub mod evm_key_binding;
 pub mod game_move;
 pub mod board;
+pub mod board_checkpoint;
+pub mod board_history;
 pub mod all_game_moves;
 pub mod participation_proof;
 pub mod dna_properties;
 pub mod profile;
 pub mod board_images;
+pub mod mask_assets;
+pub mod render_cache;
+pub mod token_metadata;
+pub mod board_animation;
 pub mod favourite_moves;
 
 use hdk::prelude::*;
@@ -18,16 +24,25 @@ pub fn set_cap_tokens() -> ExternResult<()> {
     fns.insert((zome_info()?.name, "svg_to_png".into()));
     fns.insert((zome_info()?.name, "get_evm_address".into()));
     fns.insert((zome_info()?.name, "get_agent_evm_address".into()));
+    fns.insert((zome_info()?.name, "get_agent_for_evm_address".into()));
+    fns.insert((zome_info()?.name, "get_contract_wallet_attestation_typed_data".into()));
     fns.insert((zome_info()?.name, "get_all_my_game_moves".into()));
     fns.insert((zome_info()?.name, "board_to_png".into()));
+    fns.insert((zome_info()?.name, "board_to_image".into()));
+    fns.insert((zome_info()?.name, "get_render_cache_stats".into()));
     fns.insert((zome_info()?.name, "get_dna_properties".into()));
+    fns.insert((zome_info()?.name, "get_game_phase".into()));
     fns.insert((zome_info()?.name, "get_profile".into()));
     fns.insert((zome_info()?.name, "get_number_of_moves".into()));
+    fns.insert((zome_info()?.name, "export_game_moves".into()));
+    fns.insert((zome_info()?.name, "audit_game_move_links".into()));
     fns.insert((zome_info()?.name, "get_latest_board".into()));
     fns.insert((zome_info()?.name, "get_board_at_move".into()));
+    fns.insert((zome_info()?.name, "get_board_history".into()));
     fns.insert((zome_info()?.name, "get_board_from_link".into()));
     fns.insert((zome_info()?.name, "get_boards_from_links".into()));
     fns.insert((zome_info()?.name, "token_id_to_metadata".into()));
+    fns.insert((zome_info()?.name, "token_id_to_image".into()));
     fns.insert((zome_info()?.name, "build_agent_participation".into()));
     fns.insert((zome_info()?.name, "get_signed_participation".into()));
 
@@ -0,0 +1,114 @@
+use image::{ImageBuffer, Luma};
+use std::collections::HashMap;
+
+// a pattern mask is only ever read for its alpha, so it is kept as a single channel
+pub type Mask = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+const MASK_ASSET_MAGIC: &[u8; 4] = b"FTM1";
+// the patterns repeat, so most blocks of this size appear many times in a mask. of the sizes
+// tried this gives the smallest assets
+pub const MASK_BLOCK_SIZE: u32 = 12;
+const HEADER_LENGTH: usize = 14;
+
+// the block starting at (left, top), padded with transparent pixels past the edge of the mask
+fn read_block(mask: &Mask, left: u32, top: u32, block_size: u32) -> Vec<u8> {
+    let mut block = Vec::with_capacity((block_size * block_size) as usize);
+    for y in top..top + block_size {
+        for x in left..left + block_size {
+            block.push(if x < mask.width() && y < mask.height() { mask.get_pixel(x, y)[0] } else { 0 });
+        }
+    }
+    block
+}
+
+// the compact mask asset format that cli_tools writes and the coordinator includes.
+// the header is "FTM1", the width and height as u32 and the block size as u16, all little endian.
+// then, zlib compressed: the number of distinct blocks as a u32, each distinct block's alpha
+// row by row, then a u32 index into the distinct blocks for every block of the mask row by row
+pub fn encode_mask(mask: &Mask, block_size: u32) -> Vec<u8> {
+    let blocks_across = mask.width().div_ceil(block_size);
+    let blocks_down = mask.height().div_ceil(block_size);
+
+    let mut distinct_blocks: Vec<Vec<u8>> = Vec::new();
+    let mut block_indices: HashMap<Vec<u8>, u32> = HashMap::new();
+    let mut layout = Vec::with_capacity((blocks_across * blocks_down) as usize);
+    for block_y in 0..blocks_down {
+        for block_x in 0..blocks_across {
+            let block = read_block(mask, block_x * block_size, block_y * block_size, block_size);
+            let index = *block_indices.entry(block.clone()).or_insert_with(|| {
+                distinct_blocks.push(block);
+                distinct_blocks.len() as u32 - 1
+            });
+            layout.push(index);
+        }
+    }
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&(distinct_blocks.len() as u32).to_le_bytes());
+    for block in &distinct_blocks {
+        body.extend_from_slice(block);
+    }
+    for index in layout {
+        body.extend_from_slice(&index.to_le_bytes());
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_LENGTH);
+    bytes.extend_from_slice(MASK_ASSET_MAGIC);
+    bytes.extend_from_slice(&mask.width().to_le_bytes());
+    bytes.extend_from_slice(&mask.height().to_le_bytes());
+    bytes.extend_from_slice(&(block_size as u16).to_le_bytes());
+    bytes.extend(miniz_oxide::deflate::compress_to_vec_zlib(&body, 10));
+    bytes
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(String::from("Mask asset is truncated"))
+}
+
+pub fn decode_mask(bytes: &[u8]) -> Result<Mask, String> {
+    if bytes.len() < HEADER_LENGTH || &bytes[0..4] != MASK_ASSET_MAGIC {
+        return Err(String::from("Not a mask asset"));
+    }
+    let width = read_u32(bytes, 4)?;
+    let height = read_u32(bytes, 8)?;
+    let block_size = u16::from_le_bytes([bytes[12], bytes[13]]) as u32;
+    if block_size == 0 {
+        return Err(String::from("Mask asset block size must not be 0"));
+    }
+    let body = miniz_oxide::inflate::decompress_to_vec_zlib(&bytes[HEADER_LENGTH..])
+        .map_err(|e| format!("Could not decompress mask asset: {:?}", e))?;
+
+    let block_length = (block_size * block_size) as usize;
+    let block_count = read_u32(&body, 0)? as usize;
+    let blocks = body
+        .get(4..4 + block_count * block_length)
+        .ok_or(String::from("Mask asset is truncated"))?;
+    let layout_start = 4 + blocks.len();
+
+    let blocks_across = width.div_ceil(block_size);
+    let blocks_down = height.div_ceil(block_size);
+    if body.len() != layout_start + (blocks_across * blocks_down) as usize * 4 {
+        return Err(String::from("Mask asset layout does not match its dimensions"));
+    }
+
+    let mut mask = Mask::new(width, height);
+    for block_y in 0..blocks_down {
+        for block_x in 0..blocks_across {
+            let index = read_u32(&body, layout_start + (block_y * blocks_across + block_x) as usize * 4)? as usize;
+            let block = blocks
+                .get(index * block_length..(index + 1) * block_length)
+                .ok_or(String::from("Mask asset refers to a block it does not have"))?;
+            let left = block_x * block_size;
+            let top = block_y * block_size;
+            for y in 0..block_size.min(height - top) {
+                for x in 0..block_size.min(width - left) {
+                    mask.put_pixel(left + x, top + y, Luma([block[(y * block_size + x) as usize]]));
+                }
+            }
+        }
+    }
+    Ok(mask)
+}
@@ -40,9 +40,12 @@ pub fn build_agent_participation(_: ()) -> ExternResult<ParticipationProof> {
                             agents_with_evm_binding.insert(agent.clone());
                         }
                         if agents_with_evm_binding.contains(&agent) {
-                            let pixels_changed = game_move.count_changes();
-                            *agent_pixels_changed.entry(agent.clone()).or_insert(0) += pixels_changed as u32;
-                            total_pixels_changed += pixels_changed as u32;
+                            // erasing a tile doesn't count towards participation, so an agent who only erased isn't ranked
+                            let pixels_changed = game_move.count_painted_pixels();
+                            if pixels_changed > 0 {
+                                *agent_pixels_changed.entry(agent.clone()).or_insert(0) += pixels_changed as u32;
+                                total_pixels_changed += pixels_changed as u32;
+                            }
                         }
                     },
                     Err(_) => return Err(wasm_error!("Could not convert record to GameMove")),
@@ -52,6 +55,14 @@ pub fn build_agent_participation(_: ()) -> ExternResult<ParticipationProof> {
         }
     }
 
+    // nothing was painted, so there is nobody to share the payout between
+    if total_pixels_changed == 0 {
+        return Ok(ParticipationProof {
+            total_pixels_changed,
+            agent_participations: vec![],
+        });
+    }
+
     let DnaProperties { nft_contract_address, payment_token_address, ..} = _get_dna_properties(())?;
 
     let agent_participations_result: ExternResult<Vec<AgentParticipation>> = agent_pixels_changed
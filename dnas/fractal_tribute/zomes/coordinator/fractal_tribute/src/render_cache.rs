@@ -0,0 +1,234 @@
+use hdk::prelude::*;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// how much rendered image data the zome keeps between calls. Large boards are megabytes each
+pub const RENDER_CACHE_BUDGET_BYTES: usize = 32 * 1024 * 1024;
+
+struct Node<V> {
+    key: u64,
+    value: V,
+    bytes: usize,
+    newer: Option<usize>,
+    older: Option<usize>,
+}
+
+// a least recently used cache that holds at most budget bytes of values. entries live in a slab
+// linked from newest to oldest, so lookups, inserts and evictions are all O(1)
+pub struct LruCache<V> {
+    map: HashMap<u64, usize>,   // key to slot in nodes
+    nodes: Vec<Option<Node<V>>>,
+    free: Vec<usize>,           // empty slots in nodes
+    newest: Option<usize>,
+    oldest: Option<usize>,
+    bytes: usize,
+    budget: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl<V> LruCache<V> {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            map: HashMap::new(),
+            nodes: Vec::new(),
+            free: Vec::new(),
+            newest: None,
+            oldest: None,
+            bytes: 0,
+            budget,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    fn node(&mut self, slot: usize) -> &mut Node<V> {
+        self.nodes[slot].as_mut().expect("Linked render cache slot is empty")
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let (newer, older) = {
+            let node = self.node(slot);
+            (node.newer.take(), node.older.take())
+        };
+        match newer {
+            Some(newer) => self.node(newer).older = older,
+            None => self.newest = older,
+        }
+        match older {
+            Some(older) => self.node(older).newer = newer,
+            None => self.oldest = newer,
+        }
+    }
+
+    fn push_newest(&mut self, slot: usize) {
+        let previous_newest = self.newest.replace(slot);
+        self.node(slot).older = previous_newest;
+        match previous_newest {
+            Some(previous_newest) => self.node(previous_newest).newer = Some(slot),
+            None => self.oldest = Some(slot),
+        }
+    }
+
+    fn remove_slot(&mut self, slot: usize) -> Node<V> {
+        self.unlink(slot);
+        let node = self.nodes[slot].take().expect("Linked render cache slot is empty");
+        self.map.remove(&node.key);
+        self.free.push(slot);
+        self.bytes -= node.bytes;
+        node
+    }
+
+    // the value for a key, marking it as the most recently used
+    pub fn get(&mut self, key: u64) -> Option<&V> {
+        match self.map.get(&key).copied() {
+            Some(slot) => {
+                self.hits += 1;
+                self.unlink(slot);
+                self.push_newest(slot);
+                Some(&self.node(slot).value)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    // stores a value that takes up bytes of memory, evicting the least recently used values until
+    // it fits. a value bigger than the whole budget is not stored
+    pub fn insert(&mut self, key: u64, value: V, bytes: usize) {
+        if let Some(slot) = self.map.get(&key).copied() {
+            self.remove_slot(slot);
+        }
+        if bytes > self.budget {
+            return;
+        }
+        while self.bytes + bytes > self.budget {
+            let Some(oldest) = self.oldest else { break };
+            self.remove_slot(oldest);
+            self.evictions += 1;
+        }
+
+        let node = Node { key, value, bytes, newer: None, older: None };
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = Some(node);
+                slot
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        self.map.insert(key, slot);
+        self.push_newest(slot);
+        self.bytes += bytes;
+    }
+
+    // drops every value, the hit and miss counts are kept
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.nodes.clear();
+        self.free.clear();
+        self.newest = None;
+        self.oldest = None;
+        self.bytes = 0;
+    }
+
+    pub fn stats(&self) -> RenderCacheStats {
+        RenderCacheStats {
+            entries: self.map.len() as u32,
+            bytes: self.bytes as u64,
+            budget_bytes: self.budget as u64,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+}
+
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct RenderCacheStats {
+    pub entries: u32,
+    pub bytes: u64,
+    pub budget_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,   // entries dropped to make room, not counting clear_render_cache
+}
+
+// rendered images by a hash of what was rendered, see board_images::board_to_png
+pub static RENDER_CACHE: Lazy<Mutex<LruCache<Vec<u8>>>> =
+    Lazy::new(|| Mutex::new(LruCache::new(RENDER_CACHE_BUDGET_BYTES)));
+
+#[hdk_extern]
+pub fn get_render_cache_stats(_: ()) -> ExternResult<RenderCacheStats> {
+    Ok(RENDER_CACHE.lock().unwrap().stats())
+}
+
+// not in the unrestricted grant, anyone could otherwise throw away everyone's renders
+#[hdk_extern]
+pub fn clear_render_cache(_: ()) -> ExternResult<()> {
+    RENDER_CACHE.lock().unwrap().clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_the_least_recently_used_values_to_stay_within_budget() {
+        let mut cache = LruCache::new(10);
+        cache.insert(1, "a", 4);
+        cache.insert(2, "b", 4);
+        assert_eq!(cache.get(1), Some(&"a"));
+        cache.insert(3, "c", 4);
+        assert_eq!(cache.get(2), None);
+        assert_eq!(cache.get(1), Some(&"a"));
+        assert_eq!(cache.get(3), Some(&"c"));
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.evictions), (2, 8, 1));
+        assert_eq!((stats.hits, stats.misses), (3, 1));
+    }
+
+    #[test]
+    fn reuses_slots_and_replaces_values() {
+        let mut cache = LruCache::new(10);
+        for key in 0..100 {
+            cache.insert(key, key, 3);
+        }
+        assert!(cache.nodes.len() <= 4);
+        cache.insert(99, 1000, 9);
+        assert_eq!(cache.get(99), Some(&1000));
+        assert_eq!(cache.stats().bytes, 9);
+    }
+
+    #[test]
+    fn does_not_store_values_over_budget() {
+        let mut cache = LruCache::new(10);
+        cache.insert(1, "a", 5);
+        cache.insert(2, "huge", 11);
+        assert_eq!(cache.get(2), None);
+        assert_eq!(cache.get(1), Some(&"a"));
+    }
+
+    #[test]
+    fn clear_keeps_the_counters() {
+        let mut cache = LruCache::new(10);
+        cache.insert(1, "a", 5);
+        cache.get(1);
+        cache.clear();
+        assert_eq!(cache.get(1), None);
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.hits, stats.misses), (0, 0, 1, 1));
+        cache.insert(2, "b", 5);
+        assert_eq!(cache.get(2), Some(&"b"));
+    }
+}
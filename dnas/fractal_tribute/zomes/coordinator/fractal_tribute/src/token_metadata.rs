@@ -0,0 +1,160 @@
+use hdk::prelude::*;
+use fractal_tribute_integrity::*;
+use fractal_tribute_integrity::dna_properties::{DnaProperties, _get_dna_properties};
+use ethers_core::types::U256;
+use std::collections::BTreeSet;
+use crate::all_game_moves::*;
+use crate::board::token_id_to_link_base;
+use crate::board_checkpoint::*;
+use crate::profile::get_profile;
+
+pub const TOKEN_NAME: &str = "Fractal Tribute";
+pub const TOKEN_DESCRIPTION: &str = "A collaborative art game";
+
+// the move a token was minted for, and what is needed to describe it
+pub struct TokenMove {
+    pub move_number: u32,               // its position in the canonically ordered moves, from 1
+    pub game_move: GameMove,
+    pub timestamp: Timestamp,           // when the game move was made
+    pub creator_name: Option<String>,   // from the author's Profile, if they made one
+}
+
+// how a graphic option is named in the metadata attributes. patterns are numbered from 1, the
+// same as the mask files
+pub fn graphic_option_name(graphic_option: u8) -> String {
+    let option = graphic_option as usize;
+    if graphic_option == ERASE_GRAPHIC_OPTION {
+        String::from("Erase")
+    } else if option < GRAPHIC_OPTIONS {
+        format!("Pattern {}", option + 1)
+    } else if option < GRAPHIC_OPTIONS * 2 {
+        format!("Inverse pattern {}", option - GRAPHIC_OPTIONS + 1)
+    } else {
+        String::from("Solid")
+    }
+}
+
+fn hex_color(color: &Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+// fills in {id} in one of the metadata url templates from the dna properties
+fn token_url(template: &Option<String>, token_id: &str) -> Option<String> {
+    template.as_ref().map(|template| template.replace("{id}", token_id))
+}
+
+pub fn build_token_metadata(token_id: &str, token_move: &TokenMove, image: String, dna_properties: &DnaProperties) -> Metadata {
+    let painted: Vec<&PixelChange> = token_move.game_move.changes.iter().filter(|change| !change.is_erase()).collect();
+    let colors: BTreeSet<String> = painted.iter().map(|change| hex_color(&change.color)).collect();
+    let graphic_options: BTreeSet<u8> = painted.iter().map(|change| change.graphic_option).collect();
+
+    let mut attributes = Vec::new();
+    if let Some(creator_name) = &token_move.creator_name {
+        attributes.push(MetadataAttribute::text("Creator", creator_name.clone()));
+    }
+    attributes.push(MetadataAttribute::number("Move", token_move.move_number as u64));
+    attributes.push(MetadataAttribute::number("Pixels changed", painted.len() as u64));
+    attributes.push(MetadataAttribute::number("Pixels erased", (token_move.game_move.changes.len() - painted.len()) as u64));
+    attributes.push(MetadataAttribute::number("Colours used", colors.len() as u64));
+    attributes.push(MetadataAttribute::number("Graphic options used", graphic_options.len() as u64));
+    // a trait per value so marketplaces can filter on them
+    attributes.extend(colors.into_iter().map(|color| MetadataAttribute::text("Colour", color)));
+    attributes.extend(graphic_options.into_iter().map(|option| MetadataAttribute::text("Graphic option", graphic_option_name(option))));
+    let (seconds, _) = token_move.timestamp.as_seconds_and_nanos();
+    attributes.push(MetadataAttribute::date("Created", seconds.max(0) as u64));
+
+    Metadata {
+        name: format!("{} #{}", TOKEN_NAME, token_move.move_number),
+        description: TOKEN_DESCRIPTION.to_string(),
+        image,
+        external_url: token_url(&dna_properties.metadata_external_url, token_id),
+        animation_url: token_url(&dna_properties.metadata_animation_url, token_id),
+        attributes,
+    }
+}
+
+// the metadata json for an nft, given its token id in decimal
+#[hdk_extern]
+pub fn token_id_to_metadata(str: String) -> ExternResult<String> {
+    let token_id = U256::from_dec_str(&str).map_err(|_| wasm_error!("Could not parse token id"))?;
+    let links = get_links(token_id_to_link_base(token_id)?, LinkTypes::TokenIdToGameMove, None)?;
+    let move_hash = links
+        .first()
+        .and_then(|link| link.target.clone().into_action_hash())
+        .ok_or(wasm_error!("No game moves found for that token id"))?;
+
+    let (game_moves, _) = get_all_decoded_game_move_links()?;
+    let position = game_moves
+        .iter()
+        .position(|decoded| decoded.link.target.clone().into_action_hash() == Some(move_hash.clone()))
+        .ok_or(wasm_error!("Could not find a game move for that action hash"))?;
+    let decoded = &game_moves[position];
+    let board = board_at_move_count(&game_moves, position + 1)?;
+
+    let token_move = TokenMove {
+        move_number: position as u32 + 1,
+        game_move: decoded.game_move.clone(),
+        timestamp: decoded.timestamp,
+        creator_name: get_profile(decoded.link.author.clone()).ok().map(|profile| profile.name),
+    };
+    let metadata = build_token_metadata(&str, &token_move, board.generate_svg_data_uri(), &_get_dna_properties(())?);
+    let json = serde_json::to_string(&metadata).map_err(|_| wasm_error!("Could not serialize metadata"))?;
+    Ok(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fractal_tribute_integrity::dna_properties::test_dna_properties;
+
+    fn dna_properties() -> DnaProperties {
+        DnaProperties {
+            metadata_external_url: Some(String::from("https://example.com/token/{id}")),
+            ..test_dna_properties()
+        }
+    }
+
+    fn change(x: usize, r: u8, graphic_option: u8) -> PixelChange {
+        PixelChange { x, y: 0, color: Color { r, g: 0, b: 255 }, graphic_option }
+    }
+
+    #[test]
+    fn builds_opensea_metadata_json() {
+        let token_move = TokenMove {
+            move_number: 12,
+            game_move: GameMove {
+                changes: vec![change(0, 16, 3), change(1, 16, 20), change(2, 255, 3), PixelChange::erase(3, 0)],
+            },
+            timestamp: Timestamp::from_micros(1_700_000_000_500_000),
+            creator_name: Some(String::from("ada")),
+        };
+        let metadata = build_token_metadata("42", &token_move, String::from("data:image/svg+xml;base64,"), &dna_properties());
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&metadata).unwrap()).unwrap();
+
+        assert_eq!(json["name"], "Fractal Tribute #12");
+        assert_eq!(json["external_url"], "https://example.com/token/42");
+        assert!(json.get("animation_url").is_none());
+        let attributes = json["attributes"].as_array().unwrap();
+        let attribute = |trait_type: &str| -> Vec<serde_json::Value> {
+            attributes.iter().filter(|a| a["trait_type"] == trait_type).map(|a| a["value"].clone()).collect()
+        };
+        assert_eq!(attribute("Creator"), vec!["ada"]);
+        assert_eq!(attribute("Move"), vec![12]);
+        assert_eq!(attribute("Pixels changed"), vec![3]);
+        assert_eq!(attribute("Pixels erased"), vec![1]);
+        assert_eq!(attribute("Colours used"), vec![2]);
+        assert_eq!(attribute("Colour"), vec!["#1000ff", "#ff00ff"]);
+        assert_eq!(attribute("Graphic option"), vec!["Pattern 4", "Inverse pattern 4"]);
+        assert_eq!(attribute("Created"), vec![1_700_000_000u64]);
+        assert!(attributes.iter().any(|a| a["trait_type"] == "Created" && a["display_type"] == "date"));
+        assert!(attributes.iter().all(|a| a["trait_type"] != "Colour" || a.get("display_type").is_none()));
+    }
+
+    #[test]
+    fn names_every_graphic_option() {
+        assert_eq!(graphic_option_name(0), "Pattern 1");
+        assert_eq!(graphic_option_name(GRAPHIC_OPTIONS as u8), "Inverse pattern 1");
+        assert_eq!(graphic_option_name((GRAPHIC_OPTIONS * 2) as u8), "Solid");
+        assert_eq!(graphic_option_name(ERASE_GRAPHIC_OPTION), "Erase");
+    }
+}
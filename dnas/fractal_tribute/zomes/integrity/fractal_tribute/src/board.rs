@@ -5,11 +5,40 @@ use svg::node::element::{Rectangle, Group};
 use svg::{Document, Node};
 use serde::Deserialize;
 
-pub const BOARD_SIZE: usize = 40;
+pub const BOARD_SIZE: usize = 40;   // the board width and height when they aren't set in the dna properties
 pub const GRAPHIC_OPTIONS: usize = 17;
 #[derive(Clone, PartialEq)]
 pub struct Board {
-    pub tiles: [[Tile; BOARD_SIZE]; BOARD_SIZE],
+    pub width: usize,
+    pub height: usize,
+    pub tiles: Vec<Vec<Tile>>,   // height rows of width tiles
+}
+
+// how a tile is drawn. the svg and png renderers both draw tiles from this so they agree on
+// every graphic option
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileGraphic {
+    Empty,                                    // nothing is drawn
+    Pattern { color: Color, mask: usize },    // the mask's pattern in the colour, on white
+    Inverse { color: Color, mask: usize },    // the mask's pattern in white, on the colour
+    Solid { color: Color },
+}
+
+impl Tile {
+    pub fn graphic(&self) -> TileGraphic {
+        let (color, option) = match (self.color, self.graphic_option) {
+            (Some(color), Some(option)) => (color, option as usize),
+            _ => return TileGraphic::Empty,
+        };
+        match option {
+            option if option < GRAPHIC_OPTIONS => TileGraphic::Pattern { color, mask: option },
+            option if option < GRAPHIC_OPTIONS * 2 => TileGraphic::Inverse { color, mask: option - GRAPHIC_OPTIONS },
+            // the palette's solid tile is GRAPHIC_OPTIONS * 2. validation also allows the option
+            // after it, which the svg has always drawn solid
+            option if option <= GRAPHIC_OPTIONS * 2 + 1 => TileGraphic::Solid { color },
+            _ => TileGraphic::Empty,
+        }
+    }
 }
 
 #[hdk_entry_helper]
@@ -33,6 +62,7 @@ pub struct BoardWithMetadata {
     pub bytes: Vec<u8>,
     pub creator: AgentPubKey,
     pub creation_hash: ActionHash,
+    pub diagnostics: Vec<GameMoveLinkDiagnostic>,  // game move links skipped while replaying the board
 }
 
 #[hdk_entry_helper]
@@ -42,55 +72,197 @@ pub struct BoardWithMetadataAndId {
     pub id: Vec<u8>,
 }
 
+#[hdk_entry_helper]
+#[derive(Clone, Copy, PartialEq)]
+pub enum BoardHistoryFormat {
+    Frames,  // the whole board after every move
+    Diffs,   // only the tiles each move changed
+}
+
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct BoardHistoryInput {
+    pub from_move: u32,        // number of moves already applied to the starting board
+    pub to_move: Option<u32>,  // replay up to and including this many moves, defaults to all of them
+    pub format: BoardHistoryFormat,
+}
+
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct TileChange {
+    pub x: u32,
+    pub y: u32,
+    pub tile: Tile,
+}
+
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct BoardHistoryFrame {
+    pub move_count: u32,  // moves applied to the board after this frame
+    pub move_hash: ActionHash,
+    pub author: AgentPubKey,
+    pub timestamp: Timestamp,
+    pub bytes: Option<Vec<u8>>,  // Board::to_bytes after the move, for BoardHistoryFormat::Frames
+    pub changed_tiles: Vec<TileChange>,  // tiles whose value changed, for BoardHistoryFormat::Diffs
+}
+
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct BoardHistory {
+    pub width: u32,
+    pub height: u32,
+    pub start_bytes: Vec<u8>,  // Board::to_bytes after from_move moves
+    pub frames: Vec<BoardHistoryFrame>,
+    pub next_move: Option<u32>,  // if the page was cut short, pass this as from_move to continue
+    pub diagnostics: Vec<GameMoveLinkDiagnostic>,
+}
+
+// token metadata json in the ERC-1155 schema, with the OpenSea extensions.
+// see https://eips.ethereum.org/EIPS/eip-1155#metadata and https://docs.opensea.io/docs/metadata-standards
 #[hdk_entry_helper]
 #[derive(Clone, PartialEq)]
 pub struct Metadata {
     pub name: String,
     pub description: String,
-    pub image: String
+    pub image: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_url: Option<String>,   // a page for the token outside of marketplaces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub animation_url: Option<String>,  // a multimedia version of the image, shown in its place when set
+    pub attributes: Vec<MetadataAttribute>,
+}
+
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct MetadataAttribute {
+    pub trait_type: String,
+    pub value: MetadataValue,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_type: Option<String>,   // "number", or "date" for a value in unix seconds
+}
+
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    Text(String),
+    Number(u64),
+}
+
+impl MetadataAttribute {
+    pub fn text(trait_type: &str, value: String) -> Self {
+        Self { trait_type: trait_type.to_string(), value: MetadataValue::Text(value), display_type: None }
+    }
+
+    pub fn number(trait_type: &str, value: u64) -> Self {
+        Self { trait_type: trait_type.to_string(), value: MetadataValue::Number(value), display_type: Some(String::from("number")) }
+    }
+
+    pub fn date(trait_type: &str, unix_seconds: u64) -> Self {
+        Self { trait_type: trait_type.to_string(), value: MetadataValue::Number(unix_seconds), display_type: Some(String::from("date")) }
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new(BOARD_SIZE, BOARD_SIZE)
+    }
 }
 
 impl Board {
-    fn new() -> Self {
+    pub fn new(width: usize, height: usize) -> Self {
         Board {
-            tiles: [[Tile { color: None, graphic_option: None }; BOARD_SIZE]; BOARD_SIZE],
+            width,
+            height,
+            tiles: vec![vec![Tile { color: None, graphic_option: None }; width]; height],
         }
     }
 
     pub fn from_board_input(board_input: BoardInput) -> Result<Self, String> {
-        // Check that all rows have the correct length
-        if board_input.tiles.iter().any(|row| row.len() != BOARD_SIZE) {
-            return Err(format!("Invalid board: row length is not equal to {:?}", BOARD_SIZE));
+        let height = board_input.tiles.len();
+        let width = board_input.tiles.first().map_or(0, |row| row.len());
+
+        if width == 0 || height == 0 {
+            return Err(String::from("Invalid board: board must have at least one tile"));
         }
 
-        // Check that all columns have the correct length
-        if board_input.tiles.len() != BOARD_SIZE || board_input.tiles.iter().any(|row| row.len() != BOARD_SIZE) {
-            return Err(format!("Invalid board: column length is not equal to {:?}", BOARD_SIZE));
+        // Check that all rows have the same length
+        if board_input.tiles.iter().any(|row| row.len() != width) {
+            return Err(format!("Invalid board: row length is not equal to {:?}", width));
         }
 
-        let mut tiles = [[Tile { color: None, graphic_option: None }; BOARD_SIZE]; BOARD_SIZE];
+        let mut board = Board::new(width, height);
         for (i, row) in board_input.tiles.iter().enumerate() {
             for (j, tile_input) in row.iter().enumerate() {
-                let tile = Tile {
-                    color: tile_input.color,
-                    graphic_option: tile_input.graphic_option,
+                // a tile still holding an erase change is drawn as empty
+                let tile = match tile_input.graphic_option {
+                    Some(ERASE_GRAPHIC_OPTION) => Tile { color: None, graphic_option: None },
+                    _ => Tile {
+                        color: tile_input.color,
+                        graphic_option: tile_input.graphic_option,
+                    },
                 };
-                tiles[i][j] = tile;
+                board.tiles[i][j] = tile;
             }
         }
 
-        Ok(Board { tiles })
+        Ok(board)
     }
 
-    fn apply_game_move(&mut self, game_move_: &GameMove) {
+    // to_bytes can't tell a black tile from an empty one, so checkpoints follow it with one bit per
+    // tile, in the same order, set when the tile is painted
+    pub fn to_checkpoint_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.to_bytes();
+        let mut occupied = vec![0u8; (self.width * self.height).div_ceil(8)];
+        for (i, tile) in self.tiles.iter().flatten().enumerate() {
+            if tile.color.is_some() {
+                occupied[i / 8] |= 1 << (i % 8);
+            }
+        }
+        bytes.extend_from_slice(&occupied);
+        bytes
+    }
+
+    // the inverse of to_checkpoint_bytes
+    pub fn from_checkpoint_bytes(width: usize, height: usize, bytes: &[u8]) -> Result<Self, String> {
+        let tile_count = width * height;
+        let expected_length = tile_count * 4 + tile_count.div_ceil(8);
+        if bytes.len() != expected_length {
+            return Err(format!("Invalid board bytes: length is not equal to {:?}", expected_length));
+        }
+
+        let (tile_bytes, occupied) = bytes.split_at(tile_count * 4);
+        let mut board = Board::new(width, height);
+        for (i, tile_bytes) in tile_bytes.chunks(4).enumerate() {
+            if occupied[i / 8] & (1 << (i % 8)) != 0 {
+                board.tiles[i / width][i % width] = Tile {
+                    color: Some(Color { r: tile_bytes[0], g: tile_bytes[1], b: tile_bytes[2] }),
+                    graphic_option: Some(tile_bytes[3]),
+                };
+            } else if tile_bytes != [0, 0, 0, 0] {
+                return Err(String::from("Invalid board bytes: an empty tile has a color"));
+            }
+        }
+
+        Ok(board)
+    }
+
+    pub fn apply_game_move(&mut self, game_move_: &GameMove) {
         for change in &game_move_.changes {
-            self.tiles[change.y][change.x].color = Some(change.color);
-            self.tiles[change.y][change.x].graphic_option = Some(change.graphic_option);
+            // validation keeps changes on the board, but don't panic on a move made for another size
+            if let Some(tile) = self.tiles.get_mut(change.y).and_then(|row| row.get_mut(change.x)) {
+                if change.is_erase() {
+                    *tile = Tile { color: None, graphic_option: None };
+                } else {
+                    tile.color = Some(change.color);
+                    tile.graphic_option = Some(change.graphic_option);
+                }
+            }
         }
     }
 
-    pub fn reconstruct_from_game_moves(game_moves: &[GameMove]) -> Self {
-        let mut board = Board::new();
+    pub fn reconstruct_from_game_moves(width: usize, height: usize, game_moves: &[GameMove]) -> Self {
+        let mut board = Board::new(width, height);
         for game_move_ in game_moves {
             board.apply_game_move(game_move_);
         }
@@ -117,52 +289,56 @@ impl Board {
         bytes
     }
 
+    // tiles[row][column] is drawn at (row, column) tiles from the top left, so a pixel change's y
+    // runs across the canvas. this is how the UI has always drawn boards, so rendered boards look
+    // the way they did to the players painting them
+    pub fn canvas_position(row: usize, column: usize) -> (usize, usize) {
+        (row, column)
+    }
+
+    // the canvas size in tiles, across then down
+    pub fn canvas_tiles(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    // every tile with where it is drawn on the canvas, in tiles from the top left
+    pub fn tiles_on_canvas(&self) -> impl Iterator<Item = (usize, usize, &Tile)> {
+        self.tiles.iter().enumerate().flat_map(|(row, tiles)| {
+            tiles.iter().enumerate().map(move |(column, tile)| {
+                let (left, top) = Self::canvas_position(row, column);
+                (left, top, tile)
+            })
+        })
+    }
+
     pub fn generate_svg_document(&self) -> Document {
+        let (across, down) = self.canvas_tiles();
         let mut document = Document::new()
-            .set("viewBox", (0, 0, BOARD_SIZE * 100, BOARD_SIZE * 100));
+            .set("viewBox", (0, 0, across * 100, down * 100));
     
-        let mut groups: Vec<Group> = vec![Group::new(); GRAPHIC_OPTIONS];  // Create 16 empty groups
+        let mut groups: Vec<Group> = vec![Group::new(); GRAPHIC_OPTIONS];  // one group per mask
         let mut bg_group = Group::new();
     
-        for (x, col) in self.tiles.iter().enumerate() {
-            for (y, tile) in col.iter().enumerate() {
-                let base_x = x as u32 * 100;
-                let base_y = y as u32 * 100;
-    
-                let mut rect = Rectangle::new()
-                    .set("x", base_x)
-                    .set("y", base_y)
-                    .set("width", 100)
-                    .set("height", 100);
-    
-                let mut bg_rect = Rectangle::new()
-                    .set("x", base_x)
-                    .set("y", base_y)
-                    .set("width", 100)
-                    .set("height", 100);
-    
-                if let Some(color) = tile.color {
-                    let fill = format!("rgb({},{},{})", color.r, color.g, color.b);
-                
-                    if tile.graphic_option == Some(GRAPHIC_OPTIONS as u8 * 2 + 1) {
-                        bg_rect = bg_rect.set("fill", fill);
-                    } else {
-                        if tile.graphic_option < Some(GRAPHIC_OPTIONS as u8) {
-                            bg_rect = bg_rect.set("fill", "white");
-                            rect = rect.set("fill", fill);
-                        } else {
-                            bg_rect = bg_rect.set("fill", fill);
-                            rect = rect.set("fill", "white");
-                        }
-                
-                        if let Some(mut graphic_option) = tile.graphic_option {
-                            graphic_option %= GRAPHIC_OPTIONS as u8;
-                            groups[graphic_option as usize].append(rect);
-                        }
-                    }
-                    
-                    bg_group.append(bg_rect);
+        for (left, top, tile) in self.tiles_on_canvas() {
+            let rect = |fill: String| Rectangle::new()
+                .set("x", left * 100)
+                .set("y", top * 100)
+                .set("width", 100)
+                .set("height", 100)
+                .set("fill", fill);
+            let rgb = |color: Color| format!("rgb({},{},{})", color.r, color.g, color.b);
+
+            match tile.graphic() {
+                TileGraphic::Empty => {}
+                TileGraphic::Pattern { color, mask } => {
+                    bg_group.append(rect(String::from("white")));
+                    groups[mask].append(rect(rgb(color)));
+                }
+                TileGraphic::Inverse { color, mask } => {
+                    bg_group.append(rect(rgb(color)));
+                    groups[mask].append(rect(String::from("white")));
                 }
+                TileGraphic::Solid { color } => bg_group.append(rect(rgb(color))),
             }
         }
     
@@ -186,7 +362,7 @@ impl Board {
 
     pub fn generate_svg_with_defs(&self) -> String {
         let mut document = self.generate_svg_document();
-        let defs = Text::new(include_str!("defs.svg"));
+        let defs = Text::new(Self::defs_for_size(self.width.max(self.height)));
         document = document.add(defs);
         document.to_string()
     }
@@ -196,6 +372,15 @@ impl Board {
         format!("data:image/svg+xml;base64,{}", base64::encode(svg_string.clone()))
     }
 
+    // the pattern masks in defs.svg cover a 50 tile board, stretch them for anything larger
+    fn defs_for_size(tiles: usize) -> String {
+        let mask_size = (tiles * 100).max(5000);
+        include_str!("defs.svg").replace(
+            "width=\"5000\" height=\"5000\"",
+            &format!("width=\"{}\" height=\"{}\"", mask_size, mask_size),
+        )
+    }
+
     pub fn generate_pattern_mask(option: u8) -> String {
         let mut document = Document::new()
         .set("viewBox", (0, 0, BOARD_SIZE * 100, BOARD_SIZE * 100));
@@ -221,4 +406,65 @@ impl Board {
         return document_string
     }
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+pub mod checkpoint_bytes_tests {
+    use super::*;
+
+    const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+
+    fn game_moves() -> Vec<GameMove> {
+        (0..6)
+            .map(|i| GameMove {
+                changes: vec![
+                    PixelChange { x: i, y: 0, color: BLACK, graphic_option: 0 },
+                    PixelChange { x: i, y: 1, color: BLACK, graphic_option: (GRAPHIC_OPTIONS + i) as u8 },
+                    PixelChange { x: i, y: 2, color: Color { r: 200, g: 10, b: (i * 40) as u8 }, graphic_option: 3 },
+                    PixelChange::erase(i.saturating_sub(1), 2),
+                ],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_black_tiles() {
+        let board = Board::reconstruct_from_game_moves(7, 5, &game_moves());
+        assert_eq!(board.tiles[0][0], Tile { color: Some(BLACK), graphic_option: Some(0) });
+        // the black tile with option 0 and the empty tile next to it look the same to to_bytes
+        assert_eq!(board.to_bytes()[..4], [0, 0, 0, 0]);
+
+        let read_back = Board::from_checkpoint_bytes(7, 5, &board.to_checkpoint_bytes()).unwrap();
+        assert!(read_back == board);
+        assert_eq!(read_back.generate_svg(), board.generate_svg());
+    }
+
+    #[test]
+    fn replaying_from_a_checkpoint_matches_a_full_replay() {
+        let game_moves = game_moves();
+        let full_replay = Board::reconstruct_from_game_moves(BOARD_SIZE, BOARD_SIZE, &game_moves);
+        for checkpoint_at in 0..game_moves.len() {
+            let checkpoint = Board::reconstruct_from_game_moves(BOARD_SIZE, BOARD_SIZE, &game_moves[..checkpoint_at]);
+            let mut board = Board::from_checkpoint_bytes(BOARD_SIZE, BOARD_SIZE, &checkpoint.to_checkpoint_bytes()).unwrap();
+            for game_move in &game_moves[checkpoint_at..] {
+                board.apply_game_move(game_move);
+            }
+            assert_eq!(board.to_bytes(), full_replay.to_bytes());
+            assert_eq!(board.to_checkpoint_bytes(), full_replay.to_checkpoint_bytes());
+            assert_eq!(board.generate_svg(), full_replay.generate_svg());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_checkpoint_bytes() {
+        let board = Board::reconstruct_from_game_moves(7, 5, &game_moves());
+        let bytes = board.to_checkpoint_bytes();
+        assert_eq!(bytes.len(), 7 * 5 * 4 + 5);
+        // the plain board bytes, as a checkpoint from before the occupancy bits
+        assert!(Board::from_checkpoint_bytes(7, 5, &board.to_bytes()).is_err());
+        assert!(Board::from_checkpoint_bytes(5, 7, &bytes[1..]).is_err());
+        // a color on a tile marked empty
+        let mut colored_empty_tile = Board::new(7, 5).to_checkpoint_bytes();
+        colored_empty_tile[0] = 1;
+        assert!(Board::from_checkpoint_bytes(7, 5, &colored_empty_tile).is_err());
+    }
+}
@@ -0,0 +1,349 @@
+use hdi::prelude::*;
+use hdk::prelude::Path;
+use crate::{*, dna_properties::_get_dna_properties};
+
+// a checkpoint can be published after every this many moves
+pub const BOARD_CHECKPOINT_INTERVAL: u32 = 100;
+
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct BoardCheckpoint {
+    pub board_bytes: Vec<u8>,           // Board::to_checkpoint_bytes after applying every move up to last_move_hash
+    pub last_move_hash: ActionHash,
+    pub move_count: u32,
+    pub previous_checkpoint: Option<ActionHash>,
+    pub move_hashes: Vec<ActionHash>,   // the moves applied since the previous checkpoint, in canonical order
+}
+
+pub fn board_checkpoints_path() -> Path {
+    Path::from("board_checkpoints")
+}
+
+pub fn validate_create_board_checkpoint(
+    _action: EntryCreationAction,
+    _board_checkpoint: BoardCheckpoint,
+) -> ExternResult<ValidateCallbackResult> {
+    if _board_checkpoint.move_hashes.len() != BOARD_CHECKPOINT_INTERVAL as usize {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                format!("Board checkpoints must include exactly {} moves", BOARD_CHECKPOINT_INTERVAL),
+            ),
+        )
+    }
+    if _board_checkpoint.move_hashes.last() != Some(&_board_checkpoint.last_move_hash) {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("Board checkpoint last move must be the last of its move hashes"),
+            ),
+        )
+    }
+
+    let (board_width, board_height) = match _get_dna_properties(())?.board_dimensions() {
+        Ok(board_dimensions) => board_dimensions,
+        Err(e) => return Ok(ValidateCallbackResult::Invalid(e)),
+    };
+
+    // start from the board at the previous checkpoint, or an empty board for the first one
+    let (mut board, previous_move_count, previous_last_move) = match _board_checkpoint.previous_checkpoint.clone() {
+        Some(previous_checkpoint_hash) => {
+            let record = must_get_valid_record(previous_checkpoint_hash)?;
+            if !has_entry_type(record.action(), UnitEntryTypes::BoardCheckpoint)? {
+                return Ok(
+                    ValidateCallbackResult::Invalid(
+                        String::from("Previous checkpoint must be a BoardCheckpoint"),
+                    ),
+                )
+            }
+            let previous_checkpoint: crate::BoardCheckpoint = record
+                .entry()
+                .to_app_option()
+                .map_err(|e| wasm_error!(e))?
+                .ok_or(
+                    wasm_error!(
+                        WasmErrorInner::Guest(String::from("Previous checkpoint must reference a BoardCheckpoint entry"))
+                    ),
+                )?;
+            let board = match Board::from_checkpoint_bytes(board_width, board_height, &previous_checkpoint.board_bytes) {
+                Ok(board) => board,
+                Err(e) => return Ok(ValidateCallbackResult::Invalid(e)),
+            };
+            (board, previous_checkpoint.move_count, Some(previous_checkpoint.last_move_hash))
+        }
+        None => (Board::new(board_width, board_height), 0, None),
+    };
+
+    if _board_checkpoint.move_count != previous_move_count + BOARD_CHECKPOINT_INTERVAL {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                format!("Board checkpoint move count must be {}", previous_move_count + BOARD_CHECKPOINT_INTERVAL),
+            ),
+        )
+    }
+
+    // readers replay moves in canonical order (action timestamp, then author, then action hash), so
+    // the moves must be in that order with none repeated, carrying on after the previous checkpoint's
+    let mut last_move_key = match previous_last_move {
+        Some(last_move_hash) => {
+            let action = must_get_action(last_move_hash.clone())?;
+            Some((action.hashed.content.timestamp(), action.hashed.content.author().clone(), last_move_hash))
+        }
+        None => None,
+    };
+
+    // replay the moves since the previous checkpoint and check we arrive at the same board
+    for move_hash in _board_checkpoint.move_hashes.iter() {
+        let record = must_get_valid_record(move_hash.clone())?;
+        if !has_entry_type(record.action(), UnitEntryTypes::GameMove)? {
+            return Ok(
+                ValidateCallbackResult::Invalid(
+                    String::from("Board checkpoint move hashes must all be GameMoves"),
+                ),
+            )
+        }
+        let move_key = (record.action().timestamp(), record.action().author().clone(), move_hash.clone());
+        if last_move_key.as_ref().is_some_and(|last_move_key| &move_key <= last_move_key) {
+            return Ok(
+                ValidateCallbackResult::Invalid(
+                    String::from("Board checkpoint moves must be in canonical order without repeats"),
+                ),
+            )
+        }
+        last_move_key = Some(move_key);
+        let game_move: crate::GameMove = record
+            .entry()
+            .to_app_option()
+            .map_err(|e| wasm_error!(e))?
+            .ok_or(
+                wasm_error!(
+                    WasmErrorInner::Guest(String::from("Checkpoint move hashes must reference GameMove entries"))
+                ),
+            )?;
+        board.apply_game_move(&game_move);
+    }
+
+    if board.to_checkpoint_bytes() != _board_checkpoint.board_bytes {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("Board checkpoint bytes do not match the replayed moves"),
+            ),
+        )
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+pub fn validate_update_board_checkpoint(
+    _action: Update,
+    _board_checkpoint: BoardCheckpoint,
+    _original_action: EntryCreationAction,
+    _original_board_checkpoint: BoardCheckpoint,
+) -> ExternResult<ValidateCallbackResult> {
+    Ok(
+        ValidateCallbackResult::Invalid(
+            String::from("Board checkpoints cannot be updated"),
+        ),
+    )
+}
+pub fn validate_delete_board_checkpoint(
+    _action: Delete,
+    _original_action: EntryCreationAction,
+    _original_board_checkpoint: BoardCheckpoint,
+) -> ExternResult<ValidateCallbackResult> {
+    Ok(
+        ValidateCallbackResult::Invalid(
+            String::from("Board checkpoints cannot be deleted"),
+        ),
+    )
+}
+
+pub fn validate_create_link_board_checkpoints(
+    _action: CreateLink,
+    _base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    if _base_address != AnyLinkableHash::from(board_checkpoints_path().path_entry_hash()?) {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("Board checkpoints must be linked from the board_checkpoints path"),
+            ),
+        )
+    }
+    // Check the entry type for the given action hash
+    let action_hash = ActionHash::from(target_address);
+    let record = must_get_valid_record(action_hash)?;
+    let _board_checkpoint: crate::BoardCheckpoint = record
+        .entry()
+        .to_app_option()
+        .map_err(|e| wasm_error!(e))?
+        .ok_or(
+            wasm_error!(
+                WasmErrorInner::Guest(String::from("Linked action must reference an entry"))
+            ),
+        )?;
+    // the tag carries the move count so readers can pick a checkpoint without fetching them all
+    if _tag.0 != _board_checkpoint.move_count.to_be_bytes().to_vec() {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("Board checkpoint link tag must be the checkpoint's move count"),
+            ),
+        )
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_delete_link_board_checkpoints(
+    _action: DeleteLink,
+    _original_action: CreateLink,
+    _base: AnyLinkableHash,
+    _target: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    Ok(ValidateCallbackResult::Invalid(String::from("Board checkpoint links cannot be deleted")))
+}
+
+#[cfg(test)]
+pub mod validation_tests {
+    use super::*;
+    use crate::test_hdi::*;
+
+    const INTERVAL: usize = BOARD_CHECKPOINT_INTERVAL as usize;
+
+    // every third move paints a black tile, which the plain board bytes can't tell from empty
+    fn game_move(i: usize) -> GameMove {
+        let color = if i % 3 == 0 { Color { r: 0, g: 0, b: 0 } } else { Color { r: 250, g: (i % 256) as u8, b: 0 } };
+        GameMove { changes: vec![PixelChange { x: i % BOARD_SIZE, y: i / BOARD_SIZE % BOARD_SIZE, color, graphic_option: 0 }] }
+    }
+
+    // commits the next interval of moves, applying them to the board
+    fn commit_moves(host: &TestHdi, board: &mut Board, from: usize) -> Vec<ActionHash> {
+        (from..from + INTERVAL)
+            .map(|i| {
+                board.apply_game_move(&game_move(i));
+                host.create_entry(&agent(2), i as i64, EntryTypes::GameMove(game_move(i))).action_address().clone()
+            })
+            .collect()
+    }
+
+    fn checkpoint(board: &Board, move_hashes: Vec<ActionHash>, move_count: usize, previous_checkpoint: Option<ActionHash>) -> BoardCheckpoint {
+        BoardCheckpoint {
+            board_bytes: board.to_checkpoint_bytes(),
+            last_move_hash: move_hashes.last().unwrap().clone(),
+            move_count: move_count as u32,
+            previous_checkpoint,
+            move_hashes,
+        }
+    }
+
+    // commits the checkpoint and validates it, returning its hash
+    fn validate(host: &TestHdi, board_checkpoint: BoardCheckpoint) -> (ActionHash, ValidateCallbackResult) {
+        let record = host.create_entry(&agent(1), 0, EntryTypes::BoardCheckpoint(board_checkpoint.clone()));
+        let Action::Create(create) = record.action().clone() else { unreachable!() };
+        let result = validate_create_board_checkpoint(EntryCreationAction::Create(create), board_checkpoint).unwrap();
+        (record.action_address().clone(), result)
+    }
+
+    fn is_invalid(result: ValidateCallbackResult) -> bool {
+        matches!(result, ValidateCallbackResult::Invalid(_))
+    }
+
+    #[test]
+    fn accepts_checkpoints_that_replay_from_the_previous_one() {
+        let host = TestHdi::install(dna_properties());
+        let mut board = Board::default();
+
+        let first_moves = commit_moves(&host, &mut board, 0);
+        let (first, result) = validate(&host, checkpoint(&board, first_moves, INTERVAL, None));
+        assert_eq!(result, ValidateCallbackResult::Valid);
+
+        let second_moves = commit_moves(&host, &mut board, INTERVAL);
+        let (_, result) = validate(&host, checkpoint(&board, second_moves, 2 * INTERVAL, Some(first)));
+        assert_eq!(result, ValidateCallbackResult::Valid);
+    }
+
+    #[test]
+    fn rejects_checkpoints_that_do_not_replay() {
+        let host = TestHdi::install(dna_properties());
+        let mut board = Board::default();
+        let move_hashes = commit_moves(&host, &mut board, 0);
+        let valid = checkpoint(&board, move_hashes.clone(), INTERVAL, None);
+
+        // the plain board bytes lose the black tiles
+        let lossy = BoardCheckpoint { board_bytes: board.to_bytes(), ..valid.clone() };
+        assert!(is_invalid(validate(&host, lossy).1));
+
+        let mut missing_a_move = board.clone();
+        missing_a_move.tiles[0][0] = crate::board::Tile { color: None, graphic_option: None };
+        let missing_a_move = BoardCheckpoint { board_bytes: missing_a_move.to_checkpoint_bytes(), ..valid.clone() };
+        assert!(is_invalid(validate(&host, missing_a_move).1));
+
+        let wrong_move_count = BoardCheckpoint { move_count: 2 * INTERVAL as u32, ..valid.clone() };
+        assert!(is_invalid(validate(&host, wrong_move_count).1));
+
+        let wrong_last_move = BoardCheckpoint { last_move_hash: move_hashes[0].clone(), ..valid.clone() };
+        assert!(is_invalid(validate(&host, wrong_last_move).1));
+
+        let too_few_moves = BoardCheckpoint { move_hashes: move_hashes[1..].to_vec(), ..valid.clone() };
+        assert!(is_invalid(validate(&host, too_few_moves).1));
+    }
+
+    #[test]
+    fn rejects_moves_out_of_canonical_order() {
+        let host = TestHdi::install(dna_properties());
+        let mut board = Board::default();
+        let move_hashes = commit_moves(&host, &mut board, 0);
+        let out_of_order = "Board checkpoint moves must be in canonical order without repeats";
+
+        // the moves paint different tiles, so swapping two still replays to the same board
+        let mut swapped = move_hashes.clone();
+        swapped.swap(10, 11);
+        let (_, result) = validate(&host, checkpoint(&board, swapped, INTERVAL, None));
+        assert_eq!(result, ValidateCallbackResult::Invalid(String::from(out_of_order)));
+
+        let mut repeated = move_hashes.clone();
+        repeated[11] = repeated[10].clone();
+        let (_, result) = validate(&host, checkpoint(&board, repeated, INTERVAL, None));
+        assert_eq!(result, ValidateCallbackResult::Invalid(String::from(out_of_order)));
+
+        // the next checkpoint can't go back over the moves before it
+        let (first, _) = validate(&host, checkpoint(&board, move_hashes.clone(), INTERVAL, None));
+        let mut second_moves = commit_moves(&host, &mut board, INTERVAL);
+        second_moves[0] = move_hashes[INTERVAL - 1].clone();
+        let (_, result) = validate(&host, checkpoint(&board, second_moves, 2 * INTERVAL, Some(first)));
+        assert_eq!(result, ValidateCallbackResult::Invalid(String::from(out_of_order)));
+    }
+
+    #[test]
+    fn rejects_links_to_other_entries() {
+        let host = TestHdi::install(dna_properties());
+        let mut board = Board::default();
+        let move_hashes = commit_moves(&host, &mut board, 0);
+        let (first, _) = validate(&host, checkpoint(&board, move_hashes.clone(), INTERVAL, None));
+
+        // a previous checkpoint that is a game move
+        let second_moves = commit_moves(&host, &mut board, INTERVAL);
+        let not_a_checkpoint = checkpoint(&board, second_moves.clone(), 2 * INTERVAL, Some(move_hashes[0].clone()));
+        assert!(is_invalid(validate(&host, not_a_checkpoint).1));
+
+        // a move hash that is a checkpoint
+        let mut move_hashes = second_moves;
+        move_hashes[0] = first.clone();
+        let not_a_move = checkpoint(&board, move_hashes, 2 * INTERVAL, Some(first));
+        assert!(is_invalid(validate(&host, not_a_move).1));
+    }
+
+    #[test]
+    fn tags_checkpoint_links_with_the_move_count() {
+        let host = TestHdi::install(dna_properties());
+        let mut board = Board::default();
+        let move_hashes = commit_moves(&host, &mut board, 0);
+        let (checkpoint_hash, _) = validate(&host, checkpoint(&board, move_hashes, INTERVAL, None));
+
+        let base = AnyLinkableHash::from(board_checkpoints_path().path_entry_hash().unwrap());
+        let link = |tag: LinkTag| {
+            let create_link = host.create_link(&agent(1), 0, base.clone(), checkpoint_hash.clone().into(), LinkTypes::BoardCheckpoints, tag.clone());
+            validate_create_link_board_checkpoints(create_link, base.clone(), checkpoint_hash.clone().into(), tag).unwrap()
+        };
+        assert_eq!(link(LinkTag::new((INTERVAL as u32).to_be_bytes().to_vec())), ValidateCallbackResult::Valid);
+        assert!(is_invalid(link(LinkTag::new((2 * INTERVAL as u32).to_be_bytes().to_vec()))));
+    }
+}
@@ -1,16 +1,262 @@
 use hdk::prelude::*;
+use crate::{BOARD_SIZE, Color};
 
 #[hdk_entry_helper]
 pub struct DnaProperties {
     pub nft_contract_address: String,
     pub payment_token_address: String,
+    #[serde(default)]
+    pub game_start_time: u32,                       // moves are open from the start when not set
     pub game_end_time: u32,
     pub game_master_evm_key: String,
-    pub chain_id: u32
+    pub chain_id: u32,
+    pub move_cooldown_seconds: Option<u32>,         // minimum time between two moves by the same agent
+    pub pixel_budget: Option<u32>,                  // maximum pixels an agent can change per budget window
+    pub pixel_budget_window_seconds: Option<u32>,
+    pub phases: Option<Vec<GamePhase>>,
+    pub board_width: Option<u32>,
+    pub board_height: Option<u32>,
+    pub palette: Option<Vec<Color>>,    // when set, the only colors that moves can use
+    pub metadata_external_url: Option<String>,   // token metadata external_url, {id} is replaced by the token id
+    pub metadata_animation_url: Option<String>,  // token metadata animation_url, {id} is replaced by the token id
+    pub contract_wallet_verifier_evm_key: Option<String>,   // attests to contract wallet signatures, the game master when not set
+}
+
+// the most tiles a side of the board can have, so every board can be drawn at the Small size with
+// at least a pixel a tile, and validation never has to hold a huge board
+pub const MAX_BOARD_TILES: u32 = 600;
+
+impl DnaProperties {
+    // (width, height) of the board in tiles. a dimension of 0 would leave no board to play or
+    // render, so it is treated like one that isn't set. one over MAX_BOARD_TILES is a mistake in the dna
+    pub fn board_dimensions(&self) -> Result<(usize, usize), String> {
+        let tiles = |dimension: Option<u32>| match dimension {
+            Some(tiles) if tiles > MAX_BOARD_TILES => {
+                Err(format!("board_width and board_height can be at most {}", MAX_BOARD_TILES))
+            }
+            Some(tiles) if tiles > 0 => Ok(tiles as usize),
+            _ => Ok(BOARD_SIZE),
+        };
+        Ok((tiles(self.board_width)?, tiles(self.board_height)?))
+    }
+
+    // (pixels, seconds) of the per agent pixel budget. it needs both properties, so one without the
+    // other is a mistake in the dna rather than a game without a budget
+    pub fn pixel_budget_per_window(&self) -> Result<Option<(u32, u32)>, String> {
+        match (self.pixel_budget, self.pixel_budget_window_seconds) {
+            (Some(pixel_budget), Some(window)) => Ok(Some((pixel_budget, window))),
+            (None, None) => Ok(None),
+            _ => Err(String::from("pixel_budget and pixel_budget_window_seconds must be set together")),
+        }
+    }
+
+    // the key whose attestations stand in for a contract wallet's own signature
+    pub fn contract_wallet_verifier(&self) -> &str {
+        self.contract_wallet_verifier_evm_key.as_deref().unwrap_or(&self.game_master_evm_key)
+    }
+}
+
+#[hdk_entry_helper]
+#[derive(Clone, Copy, PartialEq)]
+pub enum GamePhaseKind {
+    NotStarted,
+    OpenPlay,
+    Freeze,
+    MintingWindow,   // should line up with the additional-mint-period of the NFT flow
+    Ended,
+}
+
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct GamePhase {
+    pub kind: GamePhaseKind,
+    pub start_time: u32,
+    pub end_time: u32,
+}
+
+// the phase of the game at a time in seconds. configured phases take precedence, the first listed
+// winning where they overlap, and each runs up to but not including its end time. any time they
+// don't cover is open play from the game start time up to and including the game end time, cut
+// short by the configured phases either side of it
+pub fn game_phase_at(dna_properties: &DnaProperties, time: i64) -> GamePhase {
+    let phases = dna_properties.phases.as_deref().unwrap_or_default();
+    if let Some(phase) = phases.iter().find(|phase| phase.start_time as i64 <= time && time < phase.end_time as i64) {
+        return phase.clone();
+    }
+
+    let (kind, start_time, end_time) = if time < dna_properties.game_start_time as i64 {
+        (GamePhaseKind::NotStarted, 0, dna_properties.game_start_time)
+    } else if time > dna_properties.game_end_time as i64 {
+        (GamePhaseKind::Ended, dna_properties.game_end_time, u32::MAX)
+    } else {
+        (GamePhaseKind::OpenPlay, dna_properties.game_start_time, dna_properties.game_end_time)
+    };
+    // time isn't in any configured phase, so they all end before it or start after it
+    let previous_end = phases.iter().map(|phase| phase.end_time).filter(|end_time| *end_time as i64 <= time).max();
+    let next_start = phases.iter().map(|phase| phase.start_time).filter(|start_time| *start_time as i64 > time).min();
+    GamePhase {
+        kind,
+        start_time: previous_end.map_or(start_time, |previous_end| previous_end.max(start_time)),
+        end_time: next_start.map_or(end_time, |next_start| next_start.min(end_time)),
+    }
 }
 
 pub fn _get_dna_properties(_:()) -> ExternResult<DnaProperties> {
-    let info = hdk::info::dna_info()?;
+    let info = hdi::info::dna_info()?;
     info.properties.try_into()
         .map_err(|_| wasm_error!("Failed to deserialize properties"))
-}
\ No newline at end of file
+}
+
+// a game with no limits, for the unit tests here and in the coordinator
+#[cfg(any(test, feature = "fixtures"))]
+pub fn test_dna_properties() -> DnaProperties {
+    DnaProperties {
+        nft_contract_address: String::from("0x5FbDB2315678afecb367f032d93F642f64180aa3"),
+        payment_token_address: String::new(),
+        game_start_time: 0,
+        game_end_time: u32::MAX,
+        game_master_evm_key: String::new(),
+        chain_id: 137,
+        move_cooldown_seconds: None,
+        pixel_budget: None,
+        pixel_budget_window_seconds: None,
+        phases: None,
+        board_width: None,
+        board_height: None,
+        palette: None,
+        metadata_external_url: None,
+        metadata_animation_url: None,
+        contract_wallet_verifier_evm_key: None,
+    }
+}
+
+#[cfg(test)]
+pub mod phase_tests {
+    use super::*;
+    use crate::test_hdi;
+
+    fn phase(kind: GamePhaseKind, start_time: u32, end_time: u32) -> GamePhase {
+        GamePhase { kind, start_time, end_time }
+    }
+
+    fn dna_properties(phases: Option<Vec<GamePhase>>) -> DnaProperties {
+        DnaProperties { game_start_time: 100, game_end_time: 1000, phases, ..test_hdi::dna_properties() }
+    }
+
+    fn kind_at(dna_properties: &DnaProperties, time: i64) -> GamePhaseKind {
+        game_phase_at(dna_properties, time).kind
+    }
+
+    #[test]
+    fn plays_from_the_start_time_to_the_end_time() {
+        let dna_properties = dna_properties(None);
+        assert_eq!(game_phase_at(&dna_properties, 99), phase(GamePhaseKind::NotStarted, 0, 100));
+        assert_eq!(game_phase_at(&dna_properties, 100), phase(GamePhaseKind::OpenPlay, 100, 1000));
+        // the end time is the last second moves can be made
+        assert_eq!(game_phase_at(&dna_properties, 1000), phase(GamePhaseKind::OpenPlay, 100, 1000));
+        assert_eq!(game_phase_at(&dna_properties, 1001), phase(GamePhaseKind::Ended, 1000, u32::MAX));
+    }
+
+    #[test]
+    fn configured_phases_run_up_to_their_end_time() {
+        let dna_properties = dna_properties(Some(vec![phase(GamePhaseKind::Freeze, 200, 300)]));
+        assert_eq!(kind_at(&dna_properties, 199), GamePhaseKind::OpenPlay);
+        assert_eq!(game_phase_at(&dna_properties, 200), phase(GamePhaseKind::Freeze, 200, 300));
+        assert_eq!(kind_at(&dna_properties, 299), GamePhaseKind::Freeze);
+        assert_eq!(kind_at(&dna_properties, 300), GamePhaseKind::OpenPlay);
+    }
+
+    #[test]
+    fn open_play_stops_at_the_configured_phases_around_it() {
+        let between_phases = dna_properties(Some(vec![
+            phase(GamePhaseKind::MintingWindow, 600, 700),
+            phase(GamePhaseKind::Freeze, 200, 300),
+        ]));
+        assert_eq!(game_phase_at(&between_phases, 150), phase(GamePhaseKind::OpenPlay, 100, 200));
+        assert_eq!(game_phase_at(&between_phases, 300), phase(GamePhaseKind::OpenPlay, 300, 600));
+        assert_eq!(game_phase_at(&between_phases, 700), phase(GamePhaseKind::OpenPlay, 700, 1000));
+
+        // and so do the phases before and after the game
+        let outside_the_game = dna_properties(Some(vec![
+            phase(GamePhaseKind::Freeze, 50, 60),
+            phase(GamePhaseKind::MintingWindow, 1100, 1200),
+        ]));
+        assert_eq!(game_phase_at(&outside_the_game, 10), phase(GamePhaseKind::NotStarted, 0, 50));
+        assert_eq!(game_phase_at(&outside_the_game, 60), phase(GamePhaseKind::NotStarted, 60, 100));
+        assert_eq!(game_phase_at(&outside_the_game, 1050), phase(GamePhaseKind::Ended, 1000, 1100));
+        assert_eq!(game_phase_at(&outside_the_game, 1200), phase(GamePhaseKind::Ended, 1200, u32::MAX));
+    }
+
+    #[test]
+    fn the_first_of_overlapping_phases_wins() {
+        let dna_properties = dna_properties(Some(vec![
+            phase(GamePhaseKind::Freeze, 200, 400),
+            phase(GamePhaseKind::MintingWindow, 300, 500),
+        ]));
+        assert_eq!(kind_at(&dna_properties, 350), GamePhaseKind::Freeze);
+        assert_eq!(kind_at(&dna_properties, 400), GamePhaseKind::MintingWindow);
+        assert_eq!(kind_at(&dna_properties, 500), GamePhaseKind::OpenPlay);
+    }
+
+    #[test]
+    fn configured_phases_can_fall_outside_the_game() {
+        let dna_properties = dna_properties(Some(vec![
+            phase(GamePhaseKind::OpenPlay, 50, 100),
+            phase(GamePhaseKind::MintingWindow, 1000, 2000),
+        ]));
+        assert_eq!(kind_at(&dna_properties, 49), GamePhaseKind::NotStarted);
+        assert_eq!(kind_at(&dna_properties, 50), GamePhaseKind::OpenPlay);
+        assert_eq!(kind_at(&dna_properties, 1000), GamePhaseKind::MintingWindow);
+        assert_eq!(kind_at(&dna_properties, 2000), GamePhaseKind::Ended);
+    }
+
+    // the properties a dna had before the game start time was added
+    #[derive(Serialize, Debug)]
+    struct PropertiesWithoutStartTime {
+        nft_contract_address: String,
+        payment_token_address: String,
+        game_end_time: u32,
+        game_master_evm_key: String,
+        chain_id: u32,
+    }
+
+    #[test]
+    fn reads_properties_without_a_start_time() {
+        let properties = PropertiesWithoutStartTime {
+            nft_contract_address: String::from("0x5FbDB2315678afecb367f032d93F642f64180aa3"),
+            payment_token_address: String::new(),
+            game_end_time: 1000,
+            game_master_evm_key: String::new(),
+            chain_id: 137,
+        };
+        let bytes = SerializedBytes::from(UnsafeBytes::from(holochain_serialized_bytes::encode(&properties).unwrap()));
+        let dna_properties = DnaProperties::try_from(bytes).unwrap();
+        assert_eq!(dna_properties.game_start_time, 0);
+        assert_eq!(kind_at(&dna_properties, 0), GamePhaseKind::OpenPlay);
+    }
+}
+
+#[cfg(test)]
+pub mod board_dimension_tests {
+    use super::*;
+    use crate::test_hdi;
+
+    fn board_dimensions(board_width: Option<u32>, board_height: Option<u32>) -> Result<(usize, usize), String> {
+        DnaProperties { board_width, board_height, ..test_hdi::dna_properties() }.board_dimensions()
+    }
+
+    #[test]
+    fn defaults_unset_and_zero_dimensions() {
+        assert_eq!(board_dimensions(None, None), Ok((BOARD_SIZE, BOARD_SIZE)));
+        assert_eq!(board_dimensions(Some(64), Some(32)), Ok((64, 32)));
+        assert_eq!(board_dimensions(Some(0), Some(32)), Ok((BOARD_SIZE, 32)));
+        assert_eq!(board_dimensions(Some(64), Some(0)), Ok((64, BOARD_SIZE)));
+    }
+
+    #[test]
+    fn rejects_boards_bigger_than_the_small_image() {
+        assert_eq!(board_dimensions(Some(MAX_BOARD_TILES), Some(1)), Ok((MAX_BOARD_TILES as usize, 1)));
+        assert!(board_dimensions(Some(MAX_BOARD_TILES + 1), None).is_err());
+        assert!(board_dimensions(None, Some(u32::MAX)).is_err());
+    }
+}
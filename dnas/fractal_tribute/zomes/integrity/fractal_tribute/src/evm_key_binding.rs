@@ -1,50 +1,416 @@
 use hdi::prelude::*;
 use ethers_core::types::*;
+use ethers_core::types::transaction::eip712::*;
+use ethers_core::abi::{encode, ParamType, Token};
+use ethers_core::utils::keccak256;
+use base64::Engine;
+use crate::dna_properties::{DnaProperties, _get_dna_properties};
+use crate::{has_entry_type, UnitEntryTypes};
+use crate::game_move::RATE_LIMIT_PAGE_SIZE;
+
+// the typed data domain that binding signatures are made in, see evm_key_binding_domain
+pub const EIP712_DOMAIN_NAME: &str = "Fractal Tribute";
+pub const EIP712_DOMAIN_VERSION: &str = "1";
+
+// how the wallet signed the binding. entries made before typed data signatures have no scheme
+// and are personal signs
+#[hdk_entry_helper]
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum EvmSignatureScheme {
+    #[default]
+    PersonalSign,     // over the raw 39 bytes of the agent pubkey
+    Eip712,           // over a BindHolochainAgent message in this dna's domain
+    ContractWallet,   // an EIP-1271 wallet's signature over the same message, see AttestContractWallet
+}
 
 #[hdk_entry_helper]
 #[derive(Clone, PartialEq)]
 pub struct EvmKeyBinding {
     pub evm_key: Vec<u8>,
     pub signature_bytes: Vec<u8>,
+    #[serde(default)]
+    pub signature_scheme: EvmSignatureScheme,
+    #[serde(default)]
+    pub handover: Option<EvmKeyHandover>,   // only on an update that rotates to a new key
+    #[serde(default)]
+    pub attestation: Option<Vec<u8>>,       // the verifier's signature over an AttestContractWallet, for ContractWallet bindings
 }
 
-pub fn validate_create_evm_key_binding(
-    _action: EntryCreationAction,
-    _evm_key_binding: EvmKeyBinding,
-) -> ExternResult<ValidateCallbackResult> {
+// who signed off a rotation away from the previous key
+#[hdk_entry_helper]
+#[derive(Clone, Copy, PartialEq)]
+pub enum EvmKeyHandoverSigner {
+    PreviousKey,
+    GameMaster,   // for a player who has lost the previous key
+}
+
+// an Eip712 signature over a RotateEvmKey message
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct EvmKeyHandover {
+    pub signer: EvmKeyHandoverSigner,
+    pub signature_bytes: Vec<u8>,
+}
 
-    // first verify the signature
-    let mut address_array = [0u8; 20];
-    address_array.copy_from_slice(_evm_key_binding.evm_key.as_slice());
-    let address = H160::from(address_array);
-    let signature: ethers_core::types::Signature = _evm_key_binding.signature_bytes.as_slice().try_into().unwrap();
+// the message a wallet shows when binding. the agent is written the way holochain clients
+// print it, so the player can check it against their own key
+#[derive(Clone, Debug, PartialEq)]
+pub struct BindHolochainAgent {
+    pub agent: String,
+    pub domain: EIP712Domain,
+}
 
-    let message: RecoveryMessage = _action.author().get_raw_39().try_into().ok().unwrap();
+impl BindHolochainAgent {
+    pub fn new(agent: &AgentPubKey, domain: EIP712Domain) -> Self {
+        let agent = format!("u{}", base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(agent.get_raw_39()));
+        Self { agent, domain }
+    }
+}
 
-    let verified = signature.verify(message, address);
+impl Eip712 for BindHolochainAgent {
+    type Error = Eip712Error;
 
-    if !verified.is_ok() {
-        return Ok(
-            ValidateCallbackResult::Invalid(
-                String::from("EVM pubkey binding signature is invalid"),
+    fn domain(&self) -> Result<EIP712Domain, Self::Error> {
+        Ok(self.domain.clone())
+    }
+
+    fn type_hash() -> Result<[u8; 32], Self::Error> {
+        Ok(make_type_hash(String::from("BindHolochainAgent"), &[(String::from("agent"), ParamType::String)]))
+    }
+
+    fn struct_hash(&self) -> Result<[u8; 32], Self::Error> {
+        let agent = encode_eip712_type(Token::String(self.agent.clone()));
+        Ok(keccak256(encode(&[Token::Uint(Self::type_hash()?.into()), agent])))
+    }
+}
+
+// the message the previous key, or the game master, signs to hand an agent over to a new key
+#[derive(Clone, Debug, PartialEq)]
+pub struct RotateEvmKey {
+    pub agent: String,
+    pub previous_key: Address,
+    pub new_key: Address,
+    pub domain: EIP712Domain,
+}
+
+impl RotateEvmKey {
+    pub fn new(agent: &AgentPubKey, previous_key: Address, new_key: Address, domain: EIP712Domain) -> Self {
+        Self { agent: BindHolochainAgent::new(agent, domain.clone()).agent, previous_key, new_key, domain }
+    }
+}
+
+impl Eip712 for RotateEvmKey {
+    type Error = Eip712Error;
+
+    fn domain(&self) -> Result<EIP712Domain, Self::Error> {
+        Ok(self.domain.clone())
+    }
+
+    fn type_hash() -> Result<[u8; 32], Self::Error> {
+        Ok(make_type_hash(
+            String::from("RotateEvmKey"),
+            &[
+                (String::from("agent"), ParamType::String),
+                (String::from("previousKey"), ParamType::Address),
+                (String::from("newKey"), ParamType::Address),
+            ],
+        ))
+    }
+
+    fn struct_hash(&self) -> Result<[u8; 32], Self::Error> {
+        Ok(keccak256(encode(&[
+            Token::Uint(Self::type_hash()?.into()),
+            encode_eip712_type(Token::String(self.agent.clone())),
+            Token::Address(self.previous_key),
+            Token::Address(self.new_key),
+        ])))
+    }
+}
+
+// a contract wallet's signature can only be checked by calling isValidSignature on chain, which
+// validation can't do. the contract wallet verifier makes that call off the DHT and, when the wallet
+// returns the EIP-1271 magic value, signs this message for the binding to carry
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttestContractWallet {
+    pub agent: String,
+    pub wallet: Address,
+    pub message_hash: [u8; 32],     // the BindHolochainAgent digest the wallet was asked about
+    pub signature_hash: [u8; 32],   // keccak256 of the signature the wallet accepted
+    pub domain: EIP712Domain,
+}
+
+impl AttestContractWallet {
+    pub fn new(agent: &AgentPubKey, evm_key_binding: &EvmKeyBinding, domain: EIP712Domain) -> Result<Self, String> {
+        let bind = BindHolochainAgent::new(agent, domain.clone());
+        Ok(Self {
+            message_hash: bind.encode_eip712().map_err(|e| e.to_string())?,
+            agent: bind.agent,
+            wallet: evm_address(&evm_key_binding.evm_key)?,
+            signature_hash: keccak256(&evm_key_binding.signature_bytes),
+            domain,
+        })
+    }
+}
+
+impl Eip712 for AttestContractWallet {
+    type Error = Eip712Error;
+
+    fn domain(&self) -> Result<EIP712Domain, Self::Error> {
+        Ok(self.domain.clone())
+    }
+
+    fn type_hash() -> Result<[u8; 32], Self::Error> {
+        Ok(make_type_hash(
+            String::from("AttestContractWallet"),
+            &[
+                (String::from("agent"), ParamType::String),
+                (String::from("wallet"), ParamType::Address),
+                (String::from("messageHash"), ParamType::FixedBytes(32)),
+                (String::from("signatureHash"), ParamType::FixedBytes(32)),
+            ],
+        ))
+    }
+
+    fn struct_hash(&self) -> Result<[u8; 32], Self::Error> {
+        Ok(keccak256(encode(&[
+            Token::Uint(Self::type_hash()?.into()),
+            encode_eip712_type(Token::String(self.agent.clone())),
+            Token::Address(self.wallet),
+            Token::FixedBytes(self.message_hash.to_vec()),
+            Token::FixedBytes(self.signature_hash.to_vec()),
+        ])))
+    }
+}
+
+fn evm_address(evm_key: &[u8]) -> Result<Address, String> {
+    let address_array: [u8; 20] = evm_key.try_into().map_err(|_| String::from("EVM key must be 20 bytes"))?;
+    Ok(H160::from(address_array))
+}
+
+// the chain and contract the tokens live on, and the dna hash as a salt, so a binding signature
+// can't be replayed in another game or on another chain
+pub fn evm_key_binding_domain(dna_properties: &DnaProperties, dna_hash: &DnaHash) -> Result<EIP712Domain, String> {
+    let verifying_contract: Address = dna_properties
+        .nft_contract_address
+        .parse()
+        .map_err(|_| String::from("nft_contract_address is not an EVM address"))?;
+    let mut salt = [0u8; 32];
+    salt.copy_from_slice(dna_hash.get_raw_32());
+    Ok(EIP712Domain {
+        name: Some(EIP712_DOMAIN_NAME.to_string()),
+        version: Some(EIP712_DOMAIN_VERSION.to_string()),
+        chain_id: Some(dna_properties.chain_id.into()),
+        verifying_contract: Some(verifying_contract),
+        salt: Some(salt),
+    })
+}
+
+// checks the binding was signed by its evm key, for the given agent and in the given domain
+pub fn verify_evm_key_binding_signature(
+    evm_key_binding: &EvmKeyBinding,
+    agent: &AgentPubKey,
+    domain: EIP712Domain,
+) -> Result<(), String> {
+    let address = evm_address(&evm_key_binding.evm_key)?;
+    let signature = ethers_core::types::Signature::try_from(evm_key_binding.signature_bytes.as_slice())
+        .map_err(|_| String::from("EVM pubkey binding signature is invalid"))?;
+
+    let message: RecoveryMessage = match evm_key_binding.signature_scheme {
+        EvmSignatureScheme::PersonalSign => agent.get_raw_39().into(),
+        EvmSignatureScheme::Eip712 => {
+            let digest = BindHolochainAgent::new(agent, domain).encode_eip712().map_err(|e| e.to_string())?;
+            RecoveryMessage::Hash(H256::from(digest))
+        }
+        EvmSignatureScheme::ContractWallet => {
+            return Err(String::from("Contract wallet bindings are checked by their attestation"))
+        }
+    };
+    signature
+        .verify(message, address)
+        .map_err(|_| String::from("EVM pubkey binding signature is invalid"))
+}
+
+// checks the contract wallet verifier attested that the binding's wallet accepted its signature
+pub fn verify_contract_wallet_attestation(
+    evm_key_binding: &EvmKeyBinding,
+    agent: &AgentPubKey,
+    verifier_evm_key: &str,
+    domain: EIP712Domain,
+) -> Result<(), String> {
+    let attestation = evm_key_binding
+        .attestation
+        .as_ref()
+        .ok_or(String::from("Contract wallet bindings need the verifier's attestation"))?;
+    let verifier: Address = verifier_evm_key
+        .parse()
+        .map_err(|_| String::from("contract_wallet_verifier_evm_key is not an EVM address"))?;
+    let digest = AttestContractWallet::new(agent, evm_key_binding, domain)?
+        .encode_eip712()
+        .map_err(|e| e.to_string())?;
+    let signature = ethers_core::types::Signature::try_from(attestation.as_slice())
+        .map_err(|_| String::from("Contract wallet attestation is invalid"))?;
+    signature
+        .verify(RecoveryMessage::Hash(H256::from(digest)), verifier)
+        .map_err(|_| String::from("Contract wallet attestation is invalid"))
+}
+
+// checks the handover on a rotation from previous_key to the new binding's key. a contract
+// wallet can't sign a handover validation can check, so rotating away from one needs the game master
+pub fn verify_evm_key_handover(
+    evm_key_binding: &EvmKeyBinding,
+    previous_key: &[u8],
+    agent: &AgentPubKey,
+    game_master_evm_key: &str,
+    domain: EIP712Domain,
+) -> Result<(), String> {
+    let handover = evm_key_binding
+        .handover
+        .as_ref()
+        .ok_or(String::from("Rotating an EVM key needs a handover signature"))?;
+    let signer = match handover.signer {
+        EvmKeyHandoverSigner::PreviousKey => evm_address(previous_key)?,
+        EvmKeyHandoverSigner::GameMaster => game_master_evm_key
+            .parse()
+            .map_err(|_| String::from("game_master_evm_key is not an EVM address"))?,
+    };
+    let message = RotateEvmKey::new(agent, evm_address(previous_key)?, evm_address(&evm_key_binding.evm_key)?, domain);
+    let digest = message.encode_eip712().map_err(|e| e.to_string())?;
+    let signature = ethers_core::types::Signature::try_from(handover.signature_bytes.as_slice())
+        .map_err(|_| String::from("EVM key handover signature is invalid"))?;
+    signature
+        .verify(RecoveryMessage::Hash(H256::from(digest)), signer)
+        .map_err(|_| String::from("EVM key handover signature is invalid"))
+}
+
+// the binding in force at chain_top, the latest one made or rotated to on the author's chain.
+// the chain is read a page at a time from the top and stops at the first page with a binding, so
+// an agent with a long chain doesn't make every binding and link slower to validate
+pub fn active_evm_key_binding(author: AgentPubKey, chain_top: ActionHash) -> ExternResult<Option<(ActionHash, EvmKeyBinding)>> {
+    let mut latest = None;
+    let mut page_top = Some(chain_top);
+    while let Some(top) = page_top.take() {
+        let filter = ChainFilter::new(top).take(RATE_LIMIT_PAGE_SIZE).include_cached_entries();
+        let agent_activities = must_get_agent_activity(author.clone(), filter)?;
+        for activity in &agent_activities {
+            let action = &activity.action.hashed.content;
+            let is_later = !matches!(&latest, Some((seq, _)) if action.action_seq() <= *seq);
+            if is_later && has_entry_type(action, UnitEntryTypes::EvmKeyBinding)? {
+                latest = Some((action.action_seq(), activity.action.hashed.hash.clone()));
+            }
+        }
+        if latest.is_some() || agent_activities.len() as u32 != RATE_LIMIT_PAGE_SIZE {
+            break;
+        }
+        // keep going from the oldest action in this page
+        page_top = agent_activities
+            .iter()
+            .map(|activity| &activity.action.hashed.content)
+            .min_by_key(|action| action.action_seq())
+            .and_then(|action| action.prev_action().cloned());
+    }
+    let Some((_, action_hash)) = latest else { return Ok(None) };
+
+    let record = must_get_valid_record(action_hash.clone())?;
+    let evm_key_binding: EvmKeyBinding = record
+        .entry()
+        .to_app_option()
+        .map_err(|e| wasm_error!(e))?
+        .ok_or(
+            wasm_error!(
+                WasmErrorInner::Guest(String::from("EvmKeyBinding action must reference an entry"))
             ),
-        )
+        )?;
+    Ok(Some((action_hash, evm_key_binding)))
+}
+
+pub fn validate_create_evm_key_binding(
+    _action: EntryCreationAction,
+    _evm_key_binding: EvmKeyBinding,
+) -> ExternResult<ValidateCallbackResult> {
+    let dna_properties = _get_dna_properties(())?;
+    let domain = evm_key_binding_domain(&dna_properties, &dna_info()?.hash).map_err(|e| wasm_error!(e))?;
+    let verified = match _evm_key_binding.signature_scheme {
+        EvmSignatureScheme::ContractWallet => verify_contract_wallet_attestation(
+            &_evm_key_binding,
+            _action.author(),
+            dna_properties.contract_wallet_verifier(),
+            domain,
+        ),
+        _ if _evm_key_binding.attestation.is_some() => Err(String::from("Only contract wallet bindings carry an attestation")),
+        _ => verify_evm_key_binding_signature(&_evm_key_binding, _action.author(), domain),
+    };
+    if let Err(e) = verified {
+        return Ok(ValidateCallbackResult::Invalid(e));
+    }
+
+    // one binding per agent, so there is only ever one address for their token ids and payouts.
+    // later keys come from rotating it, see validate_update_evm_key_binding
+    if let EntryCreationAction::Create(action) = &_action {
+        if _evm_key_binding.handover.is_some() {
+            return Ok(
+                ValidateCallbackResult::Invalid(
+                    String::from("Only a rotation can carry a handover"),
+                ),
+            )
+        }
+        if active_evm_key_binding(action.author.clone(), action.prev_action.clone())?.is_some() {
+            return Ok(
+                ValidateCallbackResult::Invalid(
+                    String::from("Agents can only bind one EVM key"),
+                ),
+            )
+        }
     }
 
     Ok(ValidateCallbackResult::Valid)
 }
+
+// rotates an agent to a new key. the new key signs the binding as usual, checked by
+// validate_create_evm_key_binding, and the previous key or the game master signs the handover
 pub fn validate_update_evm_key_binding(
     _action: Update,
     _evm_key_binding: EvmKeyBinding,
     _original_action: EntryCreationAction,
     _original_evm_key_binding: EvmKeyBinding,
 ) -> ExternResult<ValidateCallbackResult> {
-    Ok(
-        ValidateCallbackResult::Invalid(
-            String::from("Evm Key Bindings cannot be updated"),
-        ),
-    )
+    if _original_action.author() != &_action.author {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("Only the agent who made an EvmKeyBinding can rotate it"),
+            ),
+        )
+    }
+    if _evm_key_binding.evm_key == _original_evm_key_binding.evm_key {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("Rotating an EvmKeyBinding must change the EVM key"),
+            ),
+        )
+    }
+    // rotations form a single line, so the key in force at any point on the chain is clear
+    let active = active_evm_key_binding(_action.author.clone(), _action.prev_action.clone())?;
+    if active.map(|(action_hash, _)| action_hash) != Some(_action.original_action_address.clone()) {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("Only the agent's current EvmKeyBinding can be rotated"),
+            ),
+        )
+    }
+
+    let dna_properties = _get_dna_properties(())?;
+    let domain = evm_key_binding_domain(&dna_properties, &dna_info()?.hash).map_err(|e| wasm_error!(e))?;
+    match verify_evm_key_handover(
+        &_evm_key_binding,
+        &_original_evm_key_binding.evm_key,
+        &_action.author,
+        &dna_properties.game_master_evm_key,
+        domain,
+    ) {
+        Ok(()) => Ok(ValidateCallbackResult::Valid),
+        Err(e) => Ok(ValidateCallbackResult::Invalid(e)),
+    }
 }
+// token ids made under a key must keep resolving to it, rotate instead
 pub fn validate_delete_evm_key_binding(
     _action: Delete,
     _original_action: EntryCreationAction,
@@ -63,10 +429,101 @@ pub fn validate_create_link_agent_to_evm_key_binding(
     target_address: AnyLinkableHash,
     _tag: LinkTag,
 ) -> ExternResult<ValidateCallbackResult> {
+    // The creator of the link must be the agent themselves
+    if _action.author.as_hash().get_raw_36() != _base_address.as_hash().get_raw_36() {
+        return Ok(ValidateCallbackResult::Invalid(String::from("Agent can only link to their own EvmKeyBinding")));
+    }
     // Check the entry type for the given action hash
     let action_hash = ActionHash::from(target_address);
     let record = must_get_valid_record(action_hash)?;
-    let _evm_key_binding: crate::EvmKeyBinding = record
+    // an agent can only claim a binding they signed into their own chain
+    if record.action().author() != &_action.author {
+        return Ok(ValidateCallbackResult::Invalid(String::from("Agent can only link to an EvmKeyBinding they created")));
+    }
+    if !has_entry_type(record.action(), UnitEntryTypes::EvmKeyBinding)? {
+        return Ok(ValidateCallbackResult::Invalid(String::from("AgentToEvmKeyBinding links must point at an EvmKeyBinding")));
+    }
+    let _evm_key_binding: crate::EvmKeyBinding = match record.entry().to_app_option().map_err(|e| wasm_error!(e))? {
+        Some(evm_key_binding) => evm_key_binding,
+        None => return Ok(ValidateCallbackResult::Invalid(String::from("AgentToEvmKeyBinding links must point at an EvmKeyBinding"))),
+    };
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_delete_link_agent_to_evm_key_binding(
+    _action: DeleteLink,
+    _original_action: CreateLink,
+    _base: AnyLinkableHash,
+    _target: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    Ok(ValidateCallbackResult::Invalid(String::from("AgentToEvmKeyBinding links cannot be deleted")))
+}
+
+
+// the base of the EvmAddressToAgent links for an evm address
+pub fn evm_address_link_base(evm_key: &[u8]) -> ExternResult<ExternalHash> {
+    if evm_key.len() != 20 {
+        return Err(wasm_error!(WasmErrorInner::Guest("EVM key must be 20 bytes".to_string())));
+    }
+    // resize the 32 byte keccak hash to 36 bytes so we can use it as a Holohash
+    let mut hash = keccak256(evm_key).to_vec();
+    hash.resize(36, 0);
+    Ok(ExternalHash::from_raw_36(hash))
+}
+
+// the global index from evm address to agent. the link points at the author and its tag is the
+// action hash of the author's current binding for that address. validation can't see other
+// agents' claims, so two agents could still race for an address, get_agent_for_evm_address
+// settles that by taking the earliest claim
+pub fn validate_create_link_evm_address_to_agent(
+    action: CreateLink,
+    base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    if target_address != AnyLinkableHash::from(action.author.clone()) {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("EvmAddressToAgent links must point at their author"),
+            ),
+        )
+    }
+    let binding_hash = match ActionHash::from_raw_39(tag.into_inner()) {
+        Ok(binding_hash) => binding_hash,
+        Err(_) => {
+            return Ok(
+                ValidateCallbackResult::Invalid(
+                    String::from("EvmAddressToAgent link tag must be the action hash of an EvmKeyBinding"),
+                ),
+            )
+        }
+    };
+    let record = must_get_valid_record(binding_hash.clone())?;
+    if record.action().author() != &action.author {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("Agents can only index their own EvmKeyBinding"),
+            ),
+        )
+    }
+    if !has_entry_type(record.action(), UnitEntryTypes::EvmKeyBinding)? {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("EvmAddressToAgent link tag must be the action hash of an EvmKeyBinding"),
+            ),
+        )
+    }
+    // an address the agent has rotated away from can't be indexed to them again
+    let active = active_evm_key_binding(action.author.clone(), action.prev_action.clone())?;
+    if active.map(|(action_hash, _)| action_hash) != Some(binding_hash) {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("Agents can only index their current EvmKeyBinding"),
+            ),
+        )
+    }
+    let evm_key_binding: crate::EvmKeyBinding = record
         .entry()
         .to_app_option()
         .map_err(|e| wasm_error!(e))?
@@ -75,109 +532,419 @@ pub fn validate_create_link_agent_to_evm_key_binding(
                 WasmErrorInner::Guest(String::from("Linked action must reference an entry"))
             ),
         )?;
-    // TODO: add the appropriate validation rules
+    if base_address != AnyLinkableHash::from(evm_address_link_base(&evm_key_binding.evm_key)?) {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("EvmAddressToAgent link base must be derived from the bound EVM address"),
+            ),
+        )
+    }
     Ok(ValidateCallbackResult::Valid)
 }
 
-pub fn validate_delete_link_agent_to_evm_key_binding(
+pub fn validate_delete_link_evm_address_to_agent(
     _action: DeleteLink,
     _original_action: CreateLink,
     _base: AnyLinkableHash,
     _target: AnyLinkableHash,
     _tag: LinkTag,
 ) -> ExternResult<ValidateCallbackResult> {
-    Ok(ValidateCallbackResult::Invalid(String::from("All game moves links cannot be deleted")))
-}
-// #[cfg(test)]
-// pub mod tests {
-//     use super::*;
-//     use holochain::sweettest::*;
-//     use futures::future;
-
-//     const DNA_FILEPATH: &str = "../../../workdir/nft_payload.dna";
-
-//     pub mod evm_key_binding_tests {
-//         use super::*;
-//         use ethers_core::rand::thread_rng;
-//         use ethers_signers::{LocalWallet, Signer};
-
-//         #[tokio::test(flavor = "multi_thread")]
-//         async fn test_create_evm_binding() {
-//             let (conductors, _agents, apps) = setup_conductors(2).await;
-//             let conductor: &SweetConductor = &conductors[0];
-//             let cells = apps.cells_flattened();
-//             let alice = &cells[0];
-
-//             let wallet = LocalWallet::new(&mut thread_rng());
-
-//             // The wallet can be used to sign messages
-//             let message = alice.agent_pubkey().get_raw_39();
-//             let signature = wallet.sign_message(message).await.unwrap();
-//             assert_eq!(signature.recover(&message[..]).unwrap(), wallet.address());
-
-//             let evm_key_binding = EvmKeyBinding {
-//                 evm_key: ByteArray(wallet.address().as_bytes().to_vec()),
-//                 signature_bytes: ByteArray(signature.to_vec()),
-//             };
-
-//             let record: Record = conductor
-//                 .call(
-//                     &alice.zome("nft_payload"), 
-//                     "create_evm_key_binding", 
-//                     evm_key_binding.clone()
-//                 ).await;
-
-//             println!("{:#?}", record);
-//         }
-
-//         #[tokio::test(flavor = "multi_thread")]
-//         #[should_panic(expected = "EVM pubkey binding signature is invalid")]        
-//         async fn test_create_evm_binding_bad_sig() {
-//             let (conductors, _agents, apps) = setup_conductors(2).await;
-//             let conductor: &SweetConductor = &conductors[0];
-//             let cells = apps.cells_flattened();
-//             let alice = &cells[0];
-
-//             let wallet = LocalWallet::new(&mut thread_rng());
-//             let second_wallet = LocalWallet::new(&mut thread_rng());
-
-//             // The wallet can be used to sign messages
-//             let message = alice.agent_pubkey().get_raw_39();
-//             let signature = wallet.sign_message(message).await.unwrap();
-//             assert_eq!(signature.recover(&message[..]).unwrap(), wallet.address());
-
-//             let evm_key_binding = EvmKeyBinding {
-//                 evm_key: ByteArray(second_wallet.address().as_bytes().to_vec()),
-//                 signature_bytes: ByteArray(signature.to_vec()),
-//             };
-
-//             let record: Record = conductor
-//                 .call(
-//                     &alice.zome("nft_payload"), 
-//                     "create_evm_key_binding", 
-//                     evm_key_binding.clone()
-//                 ).await;
-
-//             println!("{:#?}", record);
-//         }
-
-//     }
-
-//     async fn setup_conductors(n: usize) -> (SweetConductorBatch, Vec<AgentPubKey>, SweetAppBatch) {
-//         let dna = SweetDnaFile::from_bundle(std::path::Path::new(DNA_FILEPATH))
-//             .await
-//             .unwrap();
-
-//         let mut conductors = SweetConductorBatch::from_standard_config(n).await;
-
-//         let all_agents: Vec<AgentPubKey> =
-//             future::join_all(conductors.iter().map(|c| SweetAgents::one(c.keystore()))).await;
-//         let apps = conductors
-//             .setup_app_for_zipped_agents("app", &all_agents, &[dna])
-//             .await
-//             .unwrap();
-
-//         conductors.exchange_peer_info().await;
-//         (conductors, all_agents, apps)
-//     }
-// }
\ No newline at end of file
+    Ok(ValidateCallbackResult::Invalid(String::from("EvmAddressToAgent links cannot be deleted")))
+}
+#[cfg(test)]
+pub mod signature_tests {
+    use super::*;
+    use crate::dna_properties::test_dna_properties;
+    use ethers_core::rand::thread_rng;
+    use ethers_signers::{LocalWallet, Signer};
+
+    fn domain(chain_id: u32, dna_byte: u8) -> EIP712Domain {
+        let dna_properties = DnaProperties { chain_id, ..test_dna_properties() };
+        evm_key_binding_domain(&dna_properties, &DnaHash::from_raw_36(vec![dna_byte; 36])).unwrap()
+    }
+
+    fn agent() -> AgentPubKey {
+        AgentPubKey::from_raw_36(vec![7; 36])
+    }
+
+    #[tokio::test]
+    async fn verifies_typed_data_bindings_in_their_own_domain_only() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let signature = wallet.sign_typed_data(&BindHolochainAgent::new(&agent(), domain(137, 1))).await.unwrap();
+        let evm_key_binding = EvmKeyBinding {
+            evm_key: wallet.address().as_bytes().to_vec(),
+            signature_bytes: signature.to_vec(),
+            signature_scheme: EvmSignatureScheme::Eip712,
+            handover: None,
+            attestation: None,
+        };
+
+        assert_eq!(verify_evm_key_binding_signature(&evm_key_binding, &agent(), domain(137, 1)), Ok(()));
+        assert!(verify_evm_key_binding_signature(&evm_key_binding, &agent(), domain(1, 1)).is_err());
+        assert!(verify_evm_key_binding_signature(&evm_key_binding, &agent(), domain(137, 2)).is_err());
+        assert!(verify_evm_key_binding_signature(&evm_key_binding, &AgentPubKey::from_raw_36(vec![8; 36]), domain(137, 1)).is_err());
+    }
+
+    #[hdk_entry_helper]
+    struct EvmKeyBindingBeforeTypedData {
+        evm_key: Vec<u8>,
+        signature_bytes: Vec<u8>,
+    }
+
+    #[tokio::test]
+    async fn still_verifies_personal_sign_bindings() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let signature = wallet.sign_message(agent().get_raw_39()).await.unwrap();
+        let old_entry = EvmKeyBindingBeforeTypedData {
+            evm_key: wallet.address().as_bytes().to_vec(),
+            signature_bytes: signature.to_vec(),
+        };
+        let evm_key_binding = EvmKeyBinding::try_from(SerializedBytes::try_from(old_entry).unwrap()).unwrap();
+
+        assert_eq!(evm_key_binding.signature_scheme, EvmSignatureScheme::PersonalSign);
+        assert_eq!(verify_evm_key_binding_signature(&evm_key_binding, &agent(), domain(137, 1)), Ok(()));
+        let typed = EvmKeyBinding { signature_scheme: EvmSignatureScheme::Eip712, ..evm_key_binding };
+        assert!(verify_evm_key_binding_signature(&typed, &agent(), domain(137, 1)).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_bindings() {
+        let evm_key_binding = EvmKeyBinding {
+            evm_key: vec![1; 19],
+            signature_bytes: vec![0; 65],
+            signature_scheme: EvmSignatureScheme::Eip712,
+            handover: None,
+            attestation: None,
+        };
+        assert_eq!(
+            verify_evm_key_binding_signature(&evm_key_binding, &agent(), domain(137, 1)),
+            Err(String::from("EVM key must be 20 bytes"))
+        );
+        let evm_key_binding = EvmKeyBinding { evm_key: vec![1; 20], signature_bytes: vec![0; 12], ..evm_key_binding };
+        assert!(verify_evm_key_binding_signature(&evm_key_binding, &agent(), domain(137, 1)).is_err());
+    }
+
+    async fn rotation(signer: &LocalWallet, handover_signer: EvmKeyHandoverSigner, previous_key: &LocalWallet, new_key: &LocalWallet) -> EvmKeyBinding {
+        let message = RotateEvmKey::new(&agent(), previous_key.address(), new_key.address(), domain(137, 1));
+        EvmKeyBinding {
+            evm_key: new_key.address().as_bytes().to_vec(),
+            signature_bytes: new_key.sign_typed_data(&BindHolochainAgent::new(&agent(), domain(137, 1))).await.unwrap().to_vec(),
+            signature_scheme: EvmSignatureScheme::Eip712,
+            handover: Some(EvmKeyHandover {
+                signer: handover_signer,
+                signature_bytes: signer.sign_typed_data(&message).await.unwrap().to_vec(),
+            }),
+            attestation: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn verifies_handovers_from_the_previous_key_or_the_game_master() {
+        let previous_key = LocalWallet::new(&mut thread_rng());
+        let new_key = LocalWallet::new(&mut thread_rng());
+        let game_master = LocalWallet::new(&mut thread_rng());
+        let game_master_key = format!("{:?}", game_master.address());
+        let verify = |evm_key_binding: &EvmKeyBinding| {
+            verify_evm_key_handover(evm_key_binding, previous_key.address().as_bytes(), &agent(), &game_master_key, domain(137, 1))
+        };
+
+        let handed_over = rotation(&previous_key, EvmKeyHandoverSigner::PreviousKey, &previous_key, &new_key).await;
+        assert_eq!(verify(&handed_over), Ok(()));
+        assert_eq!(verify_evm_key_binding_signature(&handed_over, &agent(), domain(137, 1)), Ok(()));
+        let countersigned = rotation(&game_master, EvmKeyHandoverSigner::GameMaster, &previous_key, &new_key).await;
+        assert_eq!(verify(&countersigned), Ok(()));
+
+        // the new key can't hand itself over, and the signer has to be the one claimed
+        let self_signed = rotation(&new_key, EvmKeyHandoverSigner::PreviousKey, &previous_key, &new_key).await;
+        assert!(verify(&self_signed).is_err());
+        let misattributed = rotation(&game_master, EvmKeyHandoverSigner::PreviousKey, &previous_key, &new_key).await;
+        assert!(verify(&misattributed).is_err());
+        let missing = EvmKeyBinding { handover: None, ..handed_over };
+        assert_eq!(verify(&missing), Err(String::from("Rotating an EVM key needs a handover signature")));
+    }
+
+    #[tokio::test]
+    async fn verifies_contract_wallets_by_the_verifiers_attestation() {
+        let verifier = LocalWallet::new(&mut thread_rng());
+        let verifier_key = format!("{:?}", verifier.address());
+        let mut evm_key_binding = EvmKeyBinding {
+            evm_key: vec![3; 20],
+            signature_bytes: vec![4; 130],   // whatever the wallet's isValidSignature accepts
+            signature_scheme: EvmSignatureScheme::ContractWallet,
+            handover: None,
+            attestation: None,
+        };
+        let verify = |evm_key_binding: &EvmKeyBinding, verifier_key: &str| {
+            verify_contract_wallet_attestation(evm_key_binding, &agent(), verifier_key, domain(137, 1))
+        };
+        assert_eq!(
+            verify(&evm_key_binding, &verifier_key),
+            Err(String::from("Contract wallet bindings need the verifier's attestation"))
+        );
+
+        let message = AttestContractWallet::new(&agent(), &evm_key_binding, domain(137, 1)).unwrap();
+        evm_key_binding.attestation = Some(verifier.sign_typed_data(&message).await.unwrap().to_vec());
+        assert_eq!(verify(&evm_key_binding, &verifier_key), Ok(()));
+        assert!(verify(&evm_key_binding, "0x74423442CEA6B5c90d13C2d7C21B0FcE723ECe6d").is_err());
+        assert!(verify_evm_key_binding_signature(&evm_key_binding, &agent(), domain(137, 1)).is_err());
+
+        // the attestation covers the signature the wallet accepted, not any signature
+        let other_signature = EvmKeyBinding { signature_bytes: vec![5; 130], ..evm_key_binding };
+        assert!(verify(&other_signature, &verifier_key).is_err());
+    }
+
+    #[test]
+    fn derives_one_link_base_per_address() {
+        assert_eq!(evm_address_link_base(&[1; 20]).unwrap(), evm_address_link_base(&[1; 20]).unwrap());
+        assert_ne!(evm_address_link_base(&[1; 20]).unwrap(), evm_address_link_base(&[2; 20]).unwrap());
+        assert!(evm_address_link_base(&[1; 32]).is_err());
+    }
+}
+
+#[cfg(test)]
+pub mod forged_link_tests {
+    use super::*;
+    use crate::*;
+    use crate::test_hdi::*;
+
+    fn alice() -> AgentPubKey {
+        agent(1)
+    }
+
+    fn bob() -> AgentPubKey {
+        agent(2)
+    }
+
+    // link validation only looks at who made the binding, not at its signature
+    fn evm_key_binding() -> EvmKeyBinding {
+        EvmKeyBinding {
+            evm_key: vec![7; 20],
+            signature_bytes: vec![0; 65],
+            signature_scheme: EvmSignatureScheme::PersonalSign,
+            handover: None,
+            attestation: None,
+        }
+    }
+
+    // commits the link as author and validates it
+    fn validate_link(host: &TestHdi, author: &AgentPubKey, base: &AgentPubKey, target: &ActionHash) -> ValidateCallbackResult {
+        let create_link = host.create_link(
+            author,
+            0,
+            base.clone().into(),
+            target.clone().into(),
+            LinkTypes::AgentToEvmKeyBinding,
+            LinkTag::new(Vec::new()),
+        );
+        validate_create_link_agent_to_evm_key_binding(
+            create_link.clone(),
+            create_link.base_address,
+            create_link.target_address,
+            create_link.tag,
+        )
+        .unwrap()
+    }
+
+    fn is_invalid(result: ValidateCallbackResult) -> bool {
+        matches!(result, ValidateCallbackResult::Invalid(_))
+    }
+
+    #[test]
+    fn accepts_a_link_from_an_agent_to_their_own_binding() {
+        let host = TestHdi::install(dna_properties());
+        let binding = host.create_entry(&alice(), 0, EntryTypes::EvmKeyBinding(evm_key_binding()));
+        assert_eq!(validate_link(&host, &alice(), &alice(), binding.action_address()), ValidateCallbackResult::Valid);
+    }
+
+    #[test]
+    fn rejects_a_link_to_another_agents_binding() {
+        let host = TestHdi::install(dna_properties());
+        let binding = host.create_entry(&alice(), 0, EntryTypes::EvmKeyBinding(evm_key_binding()));
+        assert!(is_invalid(validate_link(&host, &bob(), &bob(), binding.action_address())));
+    }
+
+    #[test]
+    fn rejects_a_link_from_another_agents_key() {
+        let host = TestHdi::install(dna_properties());
+        let binding = host.create_entry(&bob(), 0, EntryTypes::EvmKeyBinding(evm_key_binding()));
+        assert!(is_invalid(validate_link(&host, &bob(), &alice(), binding.action_address())));
+    }
+
+    // commits an EvmAddressToAgent link for the address bound in evm_key_binding, tagged with binding
+    fn validate_address_link(host: &TestHdi, author: &AgentPubKey, binding: &ActionHash) -> ValidateCallbackResult {
+        let create_link = host.create_link(
+            author,
+            0,
+            evm_address_link_base(&evm_key_binding().evm_key).unwrap().into(),
+            author.clone().into(),
+            LinkTypes::EvmAddressToAgent,
+            LinkTag::new(binding.get_raw_39().to_vec()),
+        );
+        validate_create_link_evm_address_to_agent(
+            create_link.clone(),
+            create_link.base_address,
+            create_link.target_address,
+            create_link.tag,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn indexes_an_address_to_the_current_binding() {
+        let host = TestHdi::install(dna_properties());
+        let binding = host.create_entry(&alice(), 0, EntryTypes::EvmKeyBinding(evm_key_binding()));
+        assert_eq!(validate_address_link(&host, &alice(), binding.action_address()), ValidateCallbackResult::Valid);
+    }
+
+    #[test]
+    fn rejects_indexing_an_address_to_a_binding_that_is_not_current() {
+        let host = TestHdi::install(dna_properties());
+        let binding = host.create_entry(&alice(), 0, EntryTypes::EvmKeyBinding(evm_key_binding()));
+        host.create_entry(&alice(), 1, EntryTypes::EvmKeyBinding(EvmKeyBinding { evm_key: vec![8; 20], ..evm_key_binding() }));
+        assert!(is_invalid(validate_address_link(&host, &alice(), binding.action_address())));
+    }
+
+    #[test]
+    fn rejects_indexing_an_address_to_an_entry_that_is_not_a_binding() {
+        let host = TestHdi::install(dna_properties());
+        let profile = host.create_entry(&alice(), 0, EntryTypes::Profile(Profile { name: String::from("alice") }));
+        assert!(is_invalid(validate_address_link(&host, &alice(), profile.action_address())));
+    }
+
+    fn commit_profiles(host: &TestHdi, author: &AgentPubKey, count: i64) {
+        for seconds in 0..count {
+            host.create_entry(author, seconds, EntryTypes::Profile(Profile { name: String::from("alice") }));
+        }
+    }
+
+    #[test]
+    fn finds_the_binding_pages_back_down_the_chain() {
+        let host = TestHdi::install(dna_properties());
+        let binding = host.create_entry(&alice(), 0, EntryTypes::EvmKeyBinding(evm_key_binding()));
+        commit_profiles(&host, &alice(), 45);
+        assert!(45 > RATE_LIMIT_PAGE_SIZE as i64);
+        let active = active_evm_key_binding(alice(), host.chain_top(&alice())).unwrap();
+        assert_eq!(active.map(|(action_hash, _)| action_hash), Some(binding.action_address().clone()));
+    }
+
+    #[test]
+    fn stops_reading_the_chain_at_the_latest_binding() {
+        let host = TestHdi::install(dna_properties());
+        commit_profiles(&host, &alice(), 45);
+        host.create_entry(&alice(), 50, EntryTypes::EvmKeyBinding(evm_key_binding()));
+        let rotated = host.create_entry(&alice(), 51, EntryTypes::EvmKeyBinding(EvmKeyBinding { evm_key: vec![8; 20], ..evm_key_binding() }));
+        commit_profiles(&host, &alice(), 3);
+
+        let active = active_evm_key_binding(alice(), host.chain_top(&alice())).unwrap();
+        assert_eq!(active.map(|(action_hash, binding)| (action_hash, binding.evm_key)), Some((rotated.action_address().clone(), vec![8; 20])));
+        assert!(host.activity_read() <= RATE_LIMIT_PAGE_SIZE as usize, "read {} actions", host.activity_read());
+    }
+
+    #[test]
+    fn finds_no_binding_on_a_chain_without_one() {
+        let host = TestHdi::install(dna_properties());
+        commit_profiles(&host, &alice(), 45);
+        assert!(active_evm_key_binding(alice(), host.chain_top(&alice())).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_a_link_to_an_entry_that_is_not_a_binding() {
+        let host = TestHdi::install(dna_properties());
+        let game_move = GameMove {
+            changes: vec![PixelChange { x: 0, y: 0, color: Color { r: 200, g: 10, b: 40 }, graphic_option: 0 }],
+        };
+        let record = host.create_entry(&alice(), 0, EntryTypes::GameMove(game_move));
+        assert!(is_invalid(validate_link(&host, &alice(), &alice(), record.action_address())));
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use holochain::sweettest::*;
+    use holochain::test_utils::consistency_10s;
+    use futures::future;
+
+    const DNA_FILEPATH: &str = "../../../workdir/fractal_tribute.dna";
+
+    pub mod agent_to_evm_key_binding_tests {
+        use super::*;
+        use ethers_core::rand::thread_rng;
+        use ethers_signers::{LocalWallet, Signer};
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn test_binding_link_resolves_for_other_agents() {
+            let (conductors, _agents, apps) = setup_conductors(2).await;
+            let cells = apps.cells_flattened();
+            let (alice, bob) = (&cells[0], &cells[1]);
+
+            let wallet = LocalWallet::new(&mut thread_rng());
+            let signature = wallet.sign_message(alice.agent_pubkey().get_raw_39()).await.unwrap();
+            let evm_key_binding = EvmKeyBinding {
+                evm_key: wallet.address().as_bytes().to_vec(),
+                signature_bytes: signature.to_vec(),
+                signature_scheme: EvmSignatureScheme::PersonalSign,
+                handover: None,
+                attestation: None,
+            };
+            let _record: Record = conductors[0]
+                .call(&alice.zome("fractal_tribute"), "create_evm_key_binding", evm_key_binding.clone())
+                .await;
+
+            // bob only sees the link once the authorities have validated it
+            consistency_10s([alice, bob]).await;
+            let evm_key: Vec<u8> = conductors[1]
+                .call(&bob.zome("fractal_tribute"), "get_agent_evm_address", alice.agent_pubkey().clone())
+                .await;
+            assert_eq!(evm_key, evm_key_binding.evm_key);
+            let agent: Option<AgentPubKey> = conductors[1]
+                .call(&bob.zome("fractal_tribute"), "get_agent_for_evm_address", evm_key)
+                .await;
+            assert_eq!(agent.as_ref(), Some(alice.agent_pubkey()));
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn test_binding_link_is_only_on_its_own_agent() {
+            let (conductors, _agents, apps) = setup_conductors(2).await;
+            let cells = apps.cells_flattened();
+            let (alice, bob) = (&cells[0], &cells[1]);
+
+            let wallet = LocalWallet::new(&mut thread_rng());
+            let signature = wallet.sign_message(alice.agent_pubkey().get_raw_39()).await.unwrap();
+            let evm_key_binding = EvmKeyBinding {
+                evm_key: wallet.address().as_bytes().to_vec(),
+                signature_bytes: signature.to_vec(),
+                signature_scheme: EvmSignatureScheme::PersonalSign,
+                handover: None,
+                attestation: None,
+            };
+            let _record: Record = conductors[0]
+                .call(&alice.zome("fractal_tribute"), "create_evm_key_binding", evm_key_binding)
+                .await;
+
+            consistency_10s([alice, bob]).await;
+            let bobs_evm_key: Result<Vec<u8>, _> = conductors[1]
+                .call_fallible(&bob.zome("fractal_tribute"), "get_agent_evm_address", bob.agent_pubkey().clone())
+                .await;
+            assert!(bobs_evm_key.is_err());
+        }
+    }
+
+    async fn setup_conductors(n: usize) -> (SweetConductorBatch, Vec<AgentPubKey>, SweetAppBatch) {
+        let dna = SweetDnaFile::from_bundle(std::path::Path::new(DNA_FILEPATH))
+            .await
+            .unwrap();
+
+        let mut conductors = SweetConductorBatch::from_standard_config(n).await;
+
+        let all_agents: Vec<AgentPubKey> =
+            future::join_all(conductors.iter().map(|c| SweetAgents::one(c.keystore()))).await;
+        let apps = conductors
+            .setup_app_for_zipped_agents("app", &all_agents, &[dna])
+            .await
+            .unwrap();
+
+        conductors.exchange_peer_info().await;
+        (conductors, all_agents, apps)
+    }
+}
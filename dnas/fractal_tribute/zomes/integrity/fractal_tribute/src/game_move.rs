@@ -1,11 +1,30 @@
 use hdi::prelude::*;
-use crate::{*, dna_properties::_get_dna_properties};
+use hdk::prelude::Path;
+use hdk::hash_path::path::{Component, root_hash};
+use crate::{*, dna_properties::{DnaProperties, GamePhaseKind, _get_dna_properties, game_phase_at}};
 use crate::board::GRAPHIC_OPTIONS;
 
+// game moves are linked under one child path of "all_game_moves" per hour, so no single base
+// becomes a hotspot as the game grows
+pub const GAME_MOVE_BUCKET_SECONDS: i64 = 60 * 60;
+
+pub const MAX_PIXEL_CHANGES: usize = 20;
+
+// how many actions the rate limit reads from the author's chain at a time
+pub const RATE_LIMIT_PAGE_SIZE: u32 = 20;
+
+// first byte of a versioned game move encoding. legacy encodings have no header and start with an
+// x coordinate, which was never this large
+pub const GAME_MOVE_ENCODING_V1: u8 = 0xf1;
+
+// a pixel change with this graphic option clears the tile back to empty instead of painting it.
+// erase changes always carry rgb(0,0,0), so they encode the same way whoever made them
+pub const ERASE_GRAPHIC_OPTION: u8 = u8::MAX;
+
 #[hdk_entry_helper]
 #[derive(Clone, PartialEq)]
 pub struct GameMove {
-    pub changes: Vec<PixelChange>,  // Contains up to 10 PixelChanges
+    pub changes: Vec<PixelChange>,  // Contains up to MAX_PIXEL_CHANGES PixelChanges
 }
 
 #[hdk_entry_helper]
@@ -32,37 +51,126 @@ pub struct Tile {
     graphic_option: Option<u8>,
 }
 
+impl PixelChange {
+    pub fn erase(x: usize, y: usize) -> Self {
+        PixelChange { x, y, color: Color { r: 0, g: 0, b: 0 }, graphic_option: ERASE_GRAPHIC_OPTION }
+    }
+
+    pub fn is_erase(&self) -> bool {
+        self.graphic_option == ERASE_GRAPHIC_OPTION
+    }
+}
+
+// an AllGameMoves link that readers had to leave out, and why
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct GameMoveLinkDiagnostic {
+    pub create_link_hash: ActionHash,
+    pub target: AnyLinkableHash,
+    pub author: AgentPubKey,
+    pub error: String,
+}
+
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct GameMovesWithDiagnostics {
+    pub game_moves: Vec<GameMove>,
+    pub diagnostics: Vec<GameMoveLinkDiagnostic>,
+}
+
 impl GameMove {
     // Method to count the number of changes
     pub fn count_changes(&self) -> usize {
         self.changes.len()
     }
 
-    // Parsing a game_move from a dynamic-length byte array
+    // the number of changes that paint a tile, leaving out erases
+    pub fn count_painted_pixels(&self) -> usize {
+        self.changes.iter().filter(|change| !change.is_erase()).count()
+    }
+
+    // Serialises a game move to a versioned byte array for use as an AllGameMoves link tag.
+    // After the header byte each entry is varint x, varint y, graphic option, r, g, b and a varint
+    // run length, so a row of identical changes along x is written once
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(self.changes.len() * 6);
+        let mut bytes = Vec::with_capacity(1 + self.changes.len() * 7);
+        bytes.push(GAME_MOVE_ENCODING_V1);
+
+        let mut i = 0;
+        while i < self.changes.len() {
+            let change = &self.changes[i];
+
+            let mut run_length = 1;
+            while let Some(next) = self.changes.get(i + run_length) {
+                let continues_run = next.y == change.y
+                    && next.x == change.x + run_length
+                    && next.color == change.color
+                    && next.graphic_option == change.graphic_option;
+                if !continues_run {
+                    break;
+                }
+                run_length += 1;
+            }
 
-        for change in &self.changes {
-            bytes.push(change.x as u8);
-            bytes.push(change.y as u8);
+            write_varint(&mut bytes, change.x);
+            write_varint(&mut bytes, change.y);
             bytes.push(change.graphic_option);
             bytes.push(change.color.r);
             bytes.push(change.color.g);
             bytes.push(change.color.b);
+            write_varint(&mut bytes, run_length);
+
+            i += run_length;
         }
 
         bytes
     }
 
-    // Parsing a game_move from a dynamic-length byte array
+    // Parsing a game_move from a dynamic-length byte array, in either the versioned or legacy format
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        match bytes.first() {
+            Some(&GAME_MOVE_ENCODING_V1) => Self::from_bytes_v1(&bytes[1..]),
+            _ => Self::from_legacy_bytes(bytes),
+        }
+    }
+
+    fn from_bytes_v1(bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut changes = Vec::new();
+        let mut cursor = 0;
+
+        while cursor < bytes.len() {
+            let x = read_varint(bytes, &mut cursor)?;
+            let y = read_varint(bytes, &mut cursor)?;
+            let fill = bytes.get(cursor..cursor + 4).ok_or("Invalid length")?;
+            cursor += 4;
+            let run_length = read_varint(bytes, &mut cursor)?;
+
+            if run_length == 0 {
+                return Err("Run length must be at least 1");
+            }
+            if run_length > MAX_PIXEL_CHANGES - changes.len() {
+                return Err("Maximum of 20 pixel changes per move");
+            }
+            x.checked_add(run_length).ok_or("Pixel change coordinates are too large")?;
+
+            let color = Color { r: fill[1], g: fill[2], b: fill[3] };
+            for offset in 0..run_length {
+                changes.push(PixelChange { x: x + offset, y, color, graphic_option: fill[0] });
+            }
+        }
+
+        Ok(GameMove { changes })
+    }
+
+    // the original format, six bytes per change with single byte coordinates and no header
+    fn from_legacy_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
         if bytes.len() % 6 != 0 {
             return Err("Invalid length");
         }
 
         let num_changes = bytes.len() / 6;
         
-        if num_changes > 20 {
+        if num_changes > MAX_PIXEL_CHANGES {
             return Err("Maximum of 20 pixel changes per move");
         }
 
@@ -87,36 +195,191 @@ impl GameMove {
     }
 }
 
+// a list of game moves as one byte string, for exporting the history to offline tools. each move
+// is written with to_bytes and prefixed with that length as a varint
+pub fn game_moves_to_bytes(game_moves: &[GameMove]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for game_move in game_moves {
+        let move_bytes = game_move.to_bytes();
+        write_varint(&mut bytes, move_bytes.len());
+        bytes.extend_from_slice(&move_bytes);
+    }
+    bytes
+}
+
+pub fn game_moves_from_bytes(bytes: &[u8]) -> Result<Vec<GameMove>, &'static str> {
+    let mut game_moves = Vec::new();
+    let mut cursor = 0;
+    while cursor < bytes.len() {
+        let length = read_varint(bytes, &mut cursor)?;
+        let move_bytes = bytes.get(cursor..cursor.saturating_add(length)).ok_or("Invalid length")?;
+        game_moves.push(GameMove::from_bytes(move_bytes)?);
+        cursor += length;
+    }
+    Ok(game_moves)
+}
+
+// unsigned LEB128
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], cursor: &mut usize) -> Result<usize, &'static str> {
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*cursor).ok_or("Invalid length")?;
+        *cursor += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return usize::try_from(value).map_err(|_| "Pixel change coordinates are too large");
+        }
+    }
+    Err("Varint is too long")
+}
+
 pub fn validate_create_game_move(
     _action: EntryCreationAction,
     _game_move: GameMove,
 ) -> ExternResult<ValidateCallbackResult> {
 
+    let dna_properties = _get_dna_properties(())?;
+    let (board_width, board_height) = match dna_properties.board_dimensions() {
+        Ok(board_dimensions) => board_dimensions,
+        Err(e) => return Ok(ValidateCallbackResult::Invalid(e)),
+    };
+
     // check that all of the pixel changes are within the bounds of the board
-    // we also only support 33 graphic options
+    // we also only support 35 graphic options plus erase, and only colors from the palette if there is one
     for change in _game_move.changes.iter() {
-        if change.x > BOARD_SIZE - 1 || change.y > BOARD_SIZE - 1 {
+        if change.x >= board_width || change.y >= board_height {
             return Ok(
                 ValidateCallbackResult::Invalid(
-                    String::from(format!("Pixel change coordinates must be between 0 and {}", BOARD_SIZE - 1)),
+                    format!("Pixel change coordinates must be within {} x {}", board_width, board_height),
                 ),
             )
         }
+        if change.is_erase() {
+            if change.color != (Color { r: 0, g: 0, b: 0 }) {
+                return Ok(
+                    ValidateCallbackResult::Invalid(
+                        String::from("Erase changes must have color rgb(0,0,0)"),
+                    ),
+                )
+            }
+            continue;
+        }
         if change.graphic_option > GRAPHIC_OPTIONS as u8 * 2 + 1 {
             return Ok(
                 ValidateCallbackResult::Invalid(
-                    String::from(format!("Graphic option must be between 0 and {}", GRAPHIC_OPTIONS as u8 * 2 + 1)),
+                    format!("Graphic option must be between 0 and {}", GRAPHIC_OPTIONS as u8 * 2 + 1),
                 ),
             )
         }
+        if let Some(palette) = &dna_properties.palette {
+            if !palette.contains(&change.color) {
+                return Ok(
+                    ValidateCallbackResult::Invalid(
+                        format!("Color rgb({},{},{}) is not in the game's palette", change.color.r, change.color.g, change.color.b),
+                    ),
+                )
+            }
+        }
     }
     
-    let game_end_time = _get_dna_properties(())?.game_end_time;
     let move_creation_time = _action.timestamp().as_seconds_and_nanos().0;
 
-    if move_creation_time > game_end_time.into() {
-        debug!("Game move validation failed because game has ended");
-        return Ok(ValidateCallbackResult::Invalid("Game has ended".to_string()));
+    let invalid_reason = match game_phase_at(&dna_properties, move_creation_time).kind {
+        GamePhaseKind::OpenPlay => None,
+        GamePhaseKind::NotStarted => Some("Game has not started"),
+        GamePhaseKind::Freeze => Some("Game moves are not allowed while the board is frozen"),
+        GamePhaseKind::MintingWindow => Some("Game moves are not allowed during the minting window"),
+        GamePhaseKind::Ended => Some("Game has ended"),
+    };
+    if let Some(invalid_reason) = invalid_reason {
+        debug!("Game move validation failed: {}", invalid_reason);
+        return Ok(ValidateCallbackResult::Invalid(invalid_reason.to_string()));
+    }
+
+    validate_game_move_rate(&_action, &_game_move, &dna_properties)
+}
+
+// enforces the per agent cooldown and pixel budget from the dna properties, by looking back
+// over the game moves on the author's chain. the chain is read a page at a time and only as far
+// back as the longer of the cooldown and the budget window, so a long chain doesn't make every
+// move slower to validate
+fn validate_game_move_rate(
+    action: &EntryCreationAction,
+    game_move: &GameMove,
+    dna_properties: &DnaProperties,
+) -> ExternResult<ValidateCallbackResult> {
+    let budget = match dna_properties.pixel_budget_per_window() {
+        Ok(budget) => budget,
+        Err(e) => return Ok(ValidateCallbackResult::Invalid(e)),
+    };
+    let lookback_seconds = dna_properties.move_cooldown_seconds.max(budget.map(|(_, window)| window));
+    let Some(lookback_seconds) = lookback_seconds else { return Ok(ValidateCallbackResult::Valid) };
+
+    let move_creation_time = action.timestamp().as_seconds_and_nanos().0;
+    let mut pixels_in_window = game_move.count_changes() as u64;
+    let mut chain_top = Some(action.prev_action().clone());
+
+    while let Some(page_top) = chain_top.take() {
+        let filter = ChainFilter::new(page_top).take(RATE_LIMIT_PAGE_SIZE);
+        let agent_activities = must_get_agent_activity(action.author().clone(), filter)?;
+        // keep going from the oldest action in this page unless it is past the lookback
+        let oldest = agent_activities
+            .iter()
+            .map(|activity| &activity.action.hashed.content)
+            .min_by_key(|previous_action| previous_action.action_seq());
+        if let Some(oldest) = oldest {
+            let seconds_since = move_creation_time - oldest.timestamp().as_seconds_and_nanos().0;
+            if seconds_since < lookback_seconds as i64 && agent_activities.len() as u32 == RATE_LIMIT_PAGE_SIZE {
+                chain_top = oldest.prev_action().cloned();
+            }
+        }
+
+        for activity in agent_activities {
+            let previous_action = activity.action.hashed.content;
+            if !has_entry_type(&previous_action, UnitEntryTypes::GameMove)? {
+                continue;
+            }
+
+            let seconds_since = move_creation_time - previous_action.timestamp().as_seconds_and_nanos().0;
+
+            if let Some(cooldown) = dna_properties.move_cooldown_seconds {
+                if seconds_since < cooldown as i64 {
+                    return Ok(
+                        ValidateCallbackResult::Invalid(
+                            format!("Agents must wait {} seconds between game moves", cooldown),
+                        ),
+                    )
+                }
+            }
+
+            if let Some((_, window)) = budget {
+                if seconds_since < window as i64 {
+                    if let Some(entry_hash) = previous_action.entry_hash() {
+                        let entry = must_get_entry(entry_hash.clone())?;
+                        let previous_game_move = GameMove::try_from(entry.content)?;
+                        pixels_in_window += previous_game_move.count_changes() as u64;
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some((pixel_budget, window)) = budget {
+        if pixels_in_window > pixel_budget as u64 {
+            return Ok(
+                ValidateCallbackResult::Invalid(
+                    format!("Agents can change at most {} pixels every {} seconds", pixel_budget, window),
+                ),
+            )
+        }
     }
 
     Ok(ValidateCallbackResult::Valid)
@@ -157,40 +420,30 @@ pub fn validate_create_link_tokenid_to_game_move(
     // we need to validate that the link base really is a hash of the author's evm key + game_move hash
     let game_move_bytes = action_hash.get_raw_39().to_vec();
 
-    // get the link author's evm key
-    let filter = ChainFilter::new(_action.prev_action).include_cached_entries();
-    let agent_activities = must_get_agent_activity(_action.author, filter)?;
-
-    for activity in agent_activities {
-
-        let action_type = activity.action.hashed.action_type().clone();
-
-        if let holochain_integrity_types::ActionType::Create = action_type {
-            let record = must_get_valid_record(activity.action.hashed.hash)?;
-            let app_option = record.entry.to_app_option::<EvmKeyBinding>();
-            if let Ok(Some(evm_key_binding)) = app_option {
-                let evm_key_bytes = evm_key_binding.evm_key;
+    // get the link author's evm key, the one that was active when they made the link
+    let evm_key_binding = match active_evm_key_binding(_action.author, _action.prev_action)? {
+        Some((_, evm_key_binding)) => evm_key_binding,
+        None => {
+            return Ok(
+                ValidateCallbackResult::Invalid(
+                    String::from("No EvmKeyBinding found for the author of the link"),
+                ),
+            )
+        }
+    };
 
-                let valid_link_base = create_link_base(evm_key_bytes, game_move_bytes).unwrap();
-                
-                let base = _base_address.as_hash().clone().into_external_hash().unwrap();
+    let valid_link_base = create_link_base(evm_key_binding.evm_key, game_move_bytes)?;
+    let base = _base_address.as_hash().clone().into_external_hash().unwrap();
 
-                if valid_link_base != base {
-                    return Ok(
-                        ValidateCallbackResult::Invalid(
-                            String::from("Link base address does not match a hash derived from the agent's EVM key and the content game_move bytes"),
-                        ),
-                    )
-                }
-            
-                return Ok(ValidateCallbackResult::Valid)            
-            }
-        } else {
-            continue;
-        }
+    if valid_link_base != base {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("Link base address does not match a hash derived from the agent's EVM key and the content game_move bytes"),
+            ),
+        )
     }
 
-    Ok(ValidateCallbackResult::Invalid(String::from("No EvmKeyBinding found for the author of the link")))
+    Ok(ValidateCallbackResult::Valid)
 }
 pub fn validate_delete_link_tokenid_to_game_move(
     _action: DeleteLink,
@@ -231,6 +484,25 @@ pub fn create_link_base(evm_key: Vec<u8>, content_bytes: Vec<u8>) -> Result<Exte
     return Ok(link_base);
 }
 
+// the time bucket a game move belongs to, derived from the timestamp of its create action
+pub fn game_move_bucket(timestamp: Timestamp) -> i64 {
+    timestamp.as_seconds_and_nanos().0.div_euclid(GAME_MOVE_BUCKET_SECONDS)
+}
+
+pub fn all_game_moves_path() -> Path {
+    Path::from("all_game_moves")
+}
+
+pub fn game_move_bucket_path(bucket: i64) -> Path {
+    let mut path = all_game_moves_path();
+    path.append_component(bucket.to_string().into());
+    path
+}
+
+// how far back along the author's chain an AllGameMoves link can be from its game move. create_game_move
+// only commits the links Path::ensure makes for the bucket in between
+pub const ALL_GAME_MOVES_LINK_MAX_DISTANCE: u32 = 8;
+
 pub fn validate_create_link_all_game_moves(
     _action: CreateLink,
     _base_address: AnyLinkableHash,
@@ -238,8 +510,8 @@ pub fn validate_create_link_all_game_moves(
     _tag: LinkTag,
 ) -> ExternResult<ValidateCallbackResult> {
     // Check the entry type for the given action hash
-    let action_hash = ActionHash::from(target_address);
-    let record = must_get_valid_record(action_hash)?;
+    let action_hash = ActionHash::from(target_address.clone());
+    let record = must_get_valid_record(action_hash.clone())?;
     let _game_move: crate::GameMove = record
         .entry()
         .to_app_option()
@@ -249,14 +521,59 @@ pub fn validate_create_link_all_game_moves(
                 WasmErrorInner::Guest(String::from("Linked action must reference an entry"))
             ),
         )?;
-    // TODO: add the appropriate validation rules
+
+    // readers order moves by the link author, so it must be the author of the game move
+    if record.action().author() != &_action.author {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("AllGameMoves links can only be created by the author of the game move"),
+            ),
+        )
+    }
+
+    // the link must be made from the bucket matching the time the game move was created
+    let bucket = game_move_bucket(record.action().timestamp());
+    let bucket_hash = game_move_bucket_path(bucket).path_entry_hash()?;
+    if _base_address != AnyLinkableHash::from(bucket_hash) {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                format!("AllGameMoves link base must be the path for time bucket {}", bucket),
+            ),
+        )
+    }
+
+    // the tag may be in any supported encoding, so compare the decoded move
     let bytes = _tag.0;
-    let game_move_bytes = _game_move.to_bytes();
-    // debug!("game_move_bytes: {:?}", game_move_bytes);
-    // debug!("bytes: {:?}", bytes);
-    if game_move_bytes != bytes {
+    if GameMove::from_bytes(&bytes).ok() != Some(_game_move) {
         return Ok(ValidateCallbackResult::Invalid(String::from("GameMove bytes do not match the link tag")));
     }
+
+    // a move linked again later would be replayed on top of newer moves without going through the
+    // game move's own validation, so the link has to follow the move and be the only one to it
+    let filter = ChainFilter::new(_action.prev_action.clone())
+        .until(action_hash.clone())
+        .take(ALL_GAME_MOVES_LINK_MAX_DISTANCE);
+    let activity = must_get_agent_activity(_action.author.clone(), filter)?;
+    if !activity.iter().any(|activity| activity.action.hashed.hash == action_hash) {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                format!("AllGameMoves links must be made within {} actions of their game move", ALL_GAME_MOVES_LINK_MAX_DISTANCE),
+            ),
+        )
+    }
+    let all_game_moves = ScopedLinkType::try_from(LinkTypes::AllGameMoves)?;
+    let linked_before = activity.iter().any(|activity| {
+        matches!(
+            &activity.action.hashed.content,
+            Action::CreateLink(create_link)
+                if create_link.zome_index == all_game_moves.zome_index
+                    && create_link.link_type == all_game_moves.zome_type
+                    && create_link.target_address == target_address
+        )
+    });
+    if linked_before {
+        return Ok(ValidateCallbackResult::Invalid(String::from("A game move can only be linked into AllGameMoves once")));
+    }
     Ok(ValidateCallbackResult::Valid)
 }
 pub fn validate_delete_link_all_game_moves(
@@ -269,6 +586,71 @@ pub fn validate_delete_link_all_game_moves(
     Ok(ValidateCallbackResult::Invalid(String::from("All game moves links cannot be deleted")))
 }
 
+// the time bucket a GameMoveBuckets link tag names. Path::ensure tags each link with the leaf
+// component of the child path
+pub fn game_move_bucket_from_tag(tag: LinkTag) -> Option<i64> {
+    let component = Component::try_from(SerializedBytes::from(UnsafeBytes::from(tag.into_inner()))).ok()?;
+    String::try_from(&component).ok()?.parse().ok()
+}
+
+pub fn validate_create_link_game_move_buckets(
+    _action: CreateLink,
+    _base_address: AnyLinkableHash,
+    _target_address: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    // ensuring a bucket path links the root to all_game_moves, then all_game_moves to the bucket
+    let all_game_moves_hash = AnyLinkableHash::from(all_game_moves_path().path_entry_hash()?);
+    if _base_address == root_hash()? {
+        if _target_address != all_game_moves_hash {
+            return Ok(
+                ValidateCallbackResult::Invalid(
+                    String::from("GameMoveBuckets links from the root must point at the all_game_moves path"),
+                ),
+            )
+        }
+        return Ok(ValidateCallbackResult::Valid);
+    }
+    if _base_address != all_game_moves_hash {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("GameMoveBuckets links must be made from the all_game_moves path"),
+            ),
+        )
+    }
+
+    let bucket = match game_move_bucket_from_tag(_tag) {
+        Some(bucket) => bucket,
+        None => return Ok(ValidateCallbackResult::Invalid(String::from("GameMoveBuckets link tag must name a time bucket"))),
+    };
+    // comparing hashes also rejects other spellings of the same number, like "007"
+    if _target_address != AnyLinkableHash::from(game_move_bucket_path(bucket).path_entry_hash()?) {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                format!("GameMoveBuckets link target must be the path for time bucket {}", bucket),
+            ),
+        )
+    }
+    // a bucket is linked when the first move in it is made, so it can't be ahead of the link
+    if bucket > game_move_bucket(_action.timestamp) {
+        return Ok(
+            ValidateCallbackResult::Invalid(
+                String::from("GameMoveBuckets links cannot be made for future time buckets"),
+            ),
+        )
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+pub fn validate_delete_link_game_move_buckets(
+    _action: DeleteLink,
+    _original_action: CreateLink,
+    _base: AnyLinkableHash,
+    _target: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    Ok(ValidateCallbackResult::Invalid(String::from("Game move bucket links cannot be deleted")))
+}
+
 pub fn validate_create_link_agent_to_game_move(
     _action: CreateLink,
     _base_address: AnyLinkableHash,
@@ -306,6 +688,339 @@ pub fn validate_delete_link_agent_to_game_move(
 
 
 
+#[cfg(test)]
+pub mod encoding_tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn pixel_change() -> impl Strategy<Value = PixelChange> {
+        (0usize..10_000, 0usize..10_000, any::<u8>(), any::<[u8; 3]>())
+            .prop_map(|(x, y, graphic_option, [r, g, b])| PixelChange { x, y, color: Color { r, g, b }, graphic_option })
+    }
+
+    // moves made of rows of identical changes, so the run length encoding gets exercised
+    fn game_move_with_runs() -> impl Strategy<Value = GameMove> {
+        prop::collection::vec((pixel_change(), 1usize..6), 0..8).prop_map(|runs| {
+            let mut changes: Vec<PixelChange> = runs
+                .into_iter()
+                .flat_map(|(change, run_length)| {
+                    (0..run_length).map(move |offset| PixelChange { x: change.x + offset, ..change.clone() })
+                })
+                .collect();
+            changes.truncate(MAX_PIXEL_CHANGES);
+            GameMove { changes }
+        })
+    }
+
+    fn to_legacy_bytes(game_move: &GameMove) -> Vec<u8> {
+        game_move
+            .changes
+            .iter()
+            .flat_map(|change| {
+                [change.x as u8, change.y as u8, change.graphic_option, change.color.r, change.color.g, change.color.b]
+            })
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn round_trips(changes in prop::collection::vec(pixel_change(), 0..=MAX_PIXEL_CHANGES)) {
+            let game_move = GameMove { changes };
+            prop_assert_eq!(GameMove::from_bytes(&game_move.to_bytes()), Ok(game_move));
+        }
+
+        #[test]
+        fn round_trips_runs(game_move in game_move_with_runs()) {
+            prop_assert_eq!(GameMove::from_bytes(&game_move.to_bytes()), Ok(game_move));
+        }
+
+        #[test]
+        fn round_trips_move_lists(game_moves in prop::collection::vec(game_move_with_runs(), 0..10)) {
+            prop_assert_eq!(game_moves_from_bytes(&game_moves_to_bytes(&game_moves)), Ok(game_moves));
+        }
+
+        #[test]
+        fn reads_legacy_bytes(changes in prop::collection::vec(pixel_change(), 1..=MAX_PIXEL_CHANGES)) {
+            // legacy moves only ever had single byte coordinates below the version header
+            let changes = changes
+                .into_iter()
+                .map(|change| PixelChange { x: change.x % 0xf1, y: change.y % 256, ..change })
+                .collect();
+            let game_move = GameMove { changes };
+            prop_assert_eq!(GameMove::from_bytes(&to_legacy_bytes(&game_move)), Ok(game_move));
+        }
+
+        #[test]
+        fn rejects_runs_past_the_limit(first_run in 1usize..=MAX_PIXEL_CHANGES, run_length in prop_oneof![Just(usize::MAX), 1usize..]) {
+            // a later run can't add up to more than the limit, even when the sum would overflow
+            let mut bytes = vec![GAME_MOVE_ENCODING_V1];
+            for run in [first_run, run_length] {
+                bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+                write_varint(&mut bytes, run);
+            }
+            let result = GameMove::from_bytes(&bytes);
+            if run_length <= MAX_PIXEL_CHANGES - first_run {
+                prop_assert_eq!(result.map(|game_move| game_move.changes.len()), Ok(first_run + run_length));
+            } else {
+                prop_assert_eq!(result, Err("Maximum of 20 pixel changes per move"));
+            }
+        }
+    }
+
+    #[test]
+    fn encodes_a_row_as_one_run() {
+        let color = Color { r: 10, g: 20, b: 30 };
+        let changes = (3..8).map(|x| PixelChange { x, y: 4, color, graphic_option: 2 }).collect();
+        let bytes = GameMove { changes }.to_bytes();
+        assert_eq!(bytes, vec![GAME_MOVE_ENCODING_V1, 3, 4, 2, 10, 20, 30, 5]);
+    }
+
+    #[test]
+    fn encodes_large_coordinates() {
+        let game_move = GameMove {
+            changes: vec![PixelChange { x: 300, y: 1, color: Color { r: 0, g: 0, b: 0 }, graphic_option: 0 }],
+        };
+        assert_eq!(game_move.to_bytes(), vec![GAME_MOVE_ENCODING_V1, 0xac, 0x02, 1, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn rejects_malformed_bytes() {
+        assert_eq!(GameMove::from_bytes(&[1, 2, 3]), Err("Invalid length"));
+        assert_eq!(GameMove::from_bytes(&[GAME_MOVE_ENCODING_V1, 1, 2, 3]), Err("Invalid length"));
+        assert_eq!(GameMove::from_bytes(&[GAME_MOVE_ENCODING_V1, 1, 2, 0, 0, 0, 0, 0]), Err("Run length must be at least 1"));
+        assert_eq!(GameMove::from_bytes(&[GAME_MOVE_ENCODING_V1, 1, 2, 0, 0, 0, 0, 21]), Err("Maximum of 20 pixel changes per move"));
+        assert_eq!(GameMove::from_bytes(&[0; 6 * 21]), Err("Maximum of 20 pixel changes per move"));
+        let mut overflowing_run = vec![GAME_MOVE_ENCODING_V1, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        write_varint(&mut overflowing_run, u64::MAX as usize);
+        assert_eq!(GameMove::from_bytes(&overflowing_run), Err("Maximum of 20 pixel changes per move"));
+    }
+
+    #[test]
+    fn erases_tiles() {
+        let paint = GameMove {
+            changes: (0..3).map(|x| PixelChange { x, y: 1, color: Color { r: 9, g: 8, b: 7 }, graphic_option: 4 }).collect(),
+        };
+        let erase = GameMove { changes: vec![PixelChange::erase(1, 1)] };
+        assert_eq!(GameMove::from_bytes(&erase.to_bytes()), Ok(erase.clone()));
+        assert_eq!(erase.count_painted_pixels(), 0);
+
+        let board = Board::reconstruct_from_game_moves(4, 4, &[paint, erase]);
+        assert_eq!(board.tiles[1][1], crate::board::Tile { color: None, graphic_option: None });
+        assert_eq!(board.tiles[1][2].graphic_option, Some(4));
+        // an erased tile is written the same as one that was never painted
+        assert_eq!(board.to_bytes(), Board::from_checkpoint_bytes(4, 4, &board.to_checkpoint_bytes()).unwrap().to_bytes());
+        assert_eq!(board.to_bytes()[(4 + 1) * 4..(4 + 2) * 4], [0, 0, 0, 0]);
+    }
+}
+
+#[cfg(test)]
+pub mod bucket_tests {
+    use super::*;
+    use crate::test_hdi::*;
+
+    fn at(seconds: i64) -> Timestamp {
+        Timestamp::from_micros(seconds * 1_000_000)
+    }
+
+    // the link Path::ensure makes from all_game_moves to a bucket
+    fn bucket_link(host: &TestHdi, seconds: i64, bucket: i64) -> (CreateLink, AnyLinkableHash, AnyLinkableHash, LinkTag) {
+        let path = game_move_bucket_path(bucket).typed(LinkTypes::GameMoveBuckets).unwrap();
+        let base = AnyLinkableHash::from(all_game_moves_path().path_entry_hash().unwrap());
+        let target = AnyLinkableHash::from(path.path_entry_hash().unwrap());
+        let tag = path.make_tag().unwrap();
+        let create_link = host.create_link(&agent(1), seconds, base.clone(), target.clone(), LinkTypes::GameMoveBuckets, tag.clone());
+        (create_link, base, target, tag)
+    }
+
+    fn is_invalid(result: ExternResult<ValidateCallbackResult>) -> bool {
+        matches!(result, Ok(ValidateCallbackResult::Invalid(_)))
+    }
+
+    #[test]
+    fn buckets_moves_by_the_hour() {
+        assert_eq!(game_move_bucket(at(0)), 0);
+        assert_eq!(game_move_bucket(at(GAME_MOVE_BUCKET_SECONDS - 1)), 0);
+        assert_eq!(game_move_bucket(at(GAME_MOVE_BUCKET_SECONDS)), 1);
+        assert_eq!(game_move_bucket(at(-1)), -1);
+        assert_eq!(game_move_bucket_path(5).as_ref().len(), 2);
+    }
+
+    #[test]
+    fn accepts_the_links_path_ensure_makes() {
+        let host = TestHdi::install(dna_properties());
+        let (create_link, base, target, tag) = bucket_link(&host, 2 * GAME_MOVE_BUCKET_SECONDS, 2);
+        assert_eq!(game_move_bucket_from_tag(tag.clone()), Some(2));
+        assert_eq!(validate_create_link_game_move_buckets(create_link, base, target, tag), Ok(ValidateCallbackResult::Valid));
+
+        let root = all_game_moves_path().typed(LinkTypes::GameMoveBuckets).unwrap();
+        let target = AnyLinkableHash::from(root.path_entry_hash().unwrap());
+        let create_link = host.create_link(&agent(1), 0, root_hash().unwrap(), target.clone(), LinkTypes::GameMoveBuckets, root.make_tag().unwrap());
+        assert_eq!(
+            validate_create_link_game_move_buckets(create_link, root_hash().unwrap(), target, root.make_tag().unwrap()),
+            Ok(ValidateCallbackResult::Valid),
+        );
+    }
+
+    #[test]
+    fn rejects_children_that_are_not_buckets() {
+        let host = TestHdi::install(dna_properties());
+        let (create_link, base, target, tag) = bucket_link(&host, 2 * GAME_MOVE_BUCKET_SECONDS, 2);
+
+        // anything hung off the root other than all_game_moves
+        assert!(is_invalid(validate_create_link_game_move_buckets(create_link.clone(), root_hash().unwrap(), target.clone(), tag.clone())));
+        // a bucket under some other base
+        assert!(is_invalid(validate_create_link_game_move_buckets(create_link.clone(), target.clone(), target.clone(), tag.clone())));
+        // a tag that doesn't name a bucket
+        let not_a_bucket = Path::from("all_game_moves.spam").typed(LinkTypes::GameMoveBuckets).unwrap().make_tag().unwrap();
+        assert!(is_invalid(validate_create_link_game_move_buckets(create_link.clone(), base.clone(), target.clone(), not_a_bucket)));
+        // a target that isn't the bucket in the tag
+        let other_bucket = AnyLinkableHash::from(game_move_bucket_path(3).path_entry_hash().unwrap());
+        assert!(is_invalid(validate_create_link_game_move_buckets(create_link.clone(), base.clone(), other_bucket, tag.clone())));
+        // another spelling of the bucket
+        let padded = Path::from("all_game_moves.002").typed(LinkTypes::GameMoveBuckets).unwrap();
+        let padded_target = AnyLinkableHash::from(padded.path_entry_hash().unwrap());
+        assert!(is_invalid(validate_create_link_game_move_buckets(create_link, base.clone(), padded_target, padded.make_tag().unwrap())));
+
+        // a bucket ahead of the link
+        let (create_link, base, target, tag) = bucket_link(&host, 2 * GAME_MOVE_BUCKET_SECONDS, 3);
+        assert!(is_invalid(validate_create_link_game_move_buckets(create_link, base, target, tag)));
+    }
+}
+
+#[cfg(test)]
+pub mod all_game_moves_link_tests {
+    use super::*;
+    use crate::test_hdi::*;
+
+    fn game_move() -> GameMove {
+        GameMove { changes: vec![PixelChange { x: 1, y: 2, color: Color { r: 1, g: 2, b: 3 }, graphic_option: 0 }] }
+    }
+
+    fn make_move(host: &TestHdi, seconds: i64) -> ActionHash {
+        host.create_entry(&agent(1), seconds, EntryTypes::GameMove(game_move())).action_address().clone()
+    }
+
+    // links the move from its bucket, the way create_game_move does, and validates the link
+    fn link_move(host: &TestHdi, seconds: i64, move_hash: &ActionHash, move_seconds: i64) -> ValidateCallbackResult {
+        let bucket = game_move_bucket(Timestamp::from_micros(move_seconds * 1_000_000));
+        let base = AnyLinkableHash::from(game_move_bucket_path(bucket).path_entry_hash().unwrap());
+        let tag = LinkTag::new(game_move().to_bytes());
+        let create_link = host.create_link(&agent(1), seconds, base.clone(), move_hash.clone().into(), LinkTypes::AllGameMoves, tag.clone());
+        validate_create_link_all_game_moves(create_link, base, move_hash.clone().into(), tag).unwrap()
+    }
+
+    fn is_invalid(result: ValidateCallbackResult) -> bool {
+        matches!(result, ValidateCallbackResult::Invalid(_))
+    }
+
+    #[test]
+    fn accepts_the_link_made_with_the_move() {
+        let host = TestHdi::install(dna_properties());
+        let move_hash = make_move(&host, 100);
+        assert_eq!(link_move(&host, 100, &move_hash, 100), ValidateCallbackResult::Valid);
+    }
+
+    #[test]
+    fn rejects_linking_a_move_twice() {
+        let host = TestHdi::install(dna_properties());
+        let move_hash = make_move(&host, 100);
+        assert_eq!(link_move(&host, 100, &move_hash, 100), ValidateCallbackResult::Valid);
+        assert!(is_invalid(link_move(&host, 101, &move_hash, 100)));
+    }
+
+    #[test]
+    fn rejects_linking_an_old_move_later() {
+        let host = TestHdi::install(dna_properties());
+        let old_move = make_move(&host, 100);
+        for i in 0..ALL_GAME_MOVES_LINK_MAX_DISTANCE as i64 {
+            make_move(&host, 200 + i);
+        }
+        assert!(is_invalid(link_move(&host, 10_000, &old_move, 100)));
+    }
+}
+
+#[cfg(test)]
+pub mod rate_tests {
+    use super::*;
+    use crate::test_hdi::*;
+
+    fn game_move(pixels: usize) -> GameMove {
+        GameMove {
+            changes: (0..pixels).map(|x| PixelChange { x, y: 0, color: Color { r: 1, g: 2, b: 3 }, graphic_option: 0 }).collect(),
+        }
+    }
+
+    // commits the move to the author's chain and validates it there
+    fn make_move(host: &TestHdi, seconds: i64, pixels: usize) -> ValidateCallbackResult {
+        let record = host.create_entry(&agent(1), seconds, EntryTypes::GameMove(game_move(pixels)));
+        let Action::Create(create) = record.action().clone() else { unreachable!() };
+        validate_create_game_move(EntryCreationAction::Create(create), game_move(pixels)).unwrap()
+    }
+
+    fn invalid(reason: &str) -> ValidateCallbackResult {
+        ValidateCallbackResult::Invalid(String::from(reason))
+    }
+
+    #[test]
+    fn enforces_the_cooldown() {
+        let host = TestHdi::install(DnaProperties { move_cooldown_seconds: Some(60), ..dna_properties() });
+        assert_eq!(make_move(&host, 1000, 1), ValidateCallbackResult::Valid);
+        assert_eq!(make_move(&host, 1059, 1), invalid("Agents must wait 60 seconds between game moves"));
+        // the rejected move is still on the chain in this test, so wait from it
+        assert_eq!(make_move(&host, 1119, 1), ValidateCallbackResult::Valid);
+    }
+
+    #[test]
+    fn enforces_the_pixel_budget() {
+        let host = TestHdi::install(DnaProperties { pixel_budget: Some(5), pixel_budget_window_seconds: Some(100), ..dna_properties() });
+        assert_eq!(make_move(&host, 0, 2), ValidateCallbackResult::Valid);
+        assert_eq!(make_move(&host, 10, 3), ValidateCallbackResult::Valid);
+        assert_eq!(make_move(&host, 20, 1), invalid("Agents can change at most 5 pixels every 100 seconds"));
+
+        // the first moves fall out of the window
+        let host = TestHdi::install(DnaProperties { pixel_budget: Some(5), pixel_budget_window_seconds: Some(100), ..dna_properties() });
+        assert_eq!(make_move(&host, 0, 2), ValidateCallbackResult::Valid);
+        assert_eq!(make_move(&host, 10, 3), ValidateCallbackResult::Valid);
+        assert_eq!(make_move(&host, 100, 2), ValidateCallbackResult::Valid);
+    }
+
+    #[test]
+    fn counts_the_budget_across_pages_of_the_chain() {
+        let host = TestHdi::install(DnaProperties { pixel_budget: Some(45), pixel_budget_window_seconds: Some(10_000), ..dna_properties() });
+        for i in 0..45 {
+            assert_eq!(make_move(&host, i * 10, 1), ValidateCallbackResult::Valid);
+        }
+        assert!(45 > RATE_LIMIT_PAGE_SIZE as i64);
+        assert_eq!(make_move(&host, 450, 1), invalid("Agents can change at most 45 pixels every 10000 seconds"));
+    }
+
+    #[test]
+    fn reads_the_chain_only_as_far_back_as_it_needs() {
+        let host = TestHdi::install(DnaProperties {
+            move_cooldown_seconds: Some(30),
+            pixel_budget: Some(100),
+            pixel_budget_window_seconds: Some(600),
+            ..dna_properties()
+        });
+        // a long history of moves a minute apart, committed without validating them
+        for i in 0..200 {
+            host.create_entry(&agent(1), i * 60, EntryTypes::GameMove(game_move(1)));
+        }
+
+        assert_eq!(make_move(&host, 200 * 60, 1), ValidateCallbackResult::Valid);
+        assert!(host.activity_read() <= RATE_LIMIT_PAGE_SIZE as usize, "read {} actions", host.activity_read());
+    }
+
+    #[test]
+    fn rejects_a_budget_without_a_window() {
+        let host = TestHdi::install(DnaProperties { pixel_budget: Some(5), ..dna_properties() });
+        let reason = "pixel_budget and pixel_budget_window_seconds must be set together";
+        assert_eq!(make_move(&host, 0, 1), invalid(reason));
+        let host = TestHdi::install(DnaProperties { pixel_budget_window_seconds: Some(60), ..dna_properties() });
+        assert_eq!(make_move(&host, 0, 1), invalid(reason));
+    }
+}
+
 // #[cfg(test)]
 // pub mod tests {
 //     use super::*;
@@ -4,11 +4,15 @@ pub mod game_move;
 pub use game_move::*;
 pub mod board;
 pub use board::*;
+pub mod board_checkpoint;
+pub use board_checkpoint::*;
 pub mod participation_proof;
 pub use participation_proof::*;
 pub mod dna_properties;
 pub mod profile;
 pub use profile::*;
+#[cfg(test)]
+pub mod test_hdi;
 
 use hdi::prelude::*;
 
@@ -20,7 +24,8 @@ pub enum EntryTypes {
     GameMove(GameMove),
     EvmKeyBinding(EvmKeyBinding),
     ParticipationProof(ParticipationProof),
-    Profile(Profile)
+    Profile(Profile),
+    BoardCheckpoint(BoardCheckpoint)
 }
 #[derive(Serialize, Deserialize)]
 #[hdk_link_types]
@@ -30,7 +35,10 @@ pub enum LinkTypes {
     AgentToEvmKeyBinding,
     AgentToProfile,
     SignedParticipationProof,
-    AgentToGameMove
+    AgentToGameMove,
+    GameMoveBuckets,
+    BoardCheckpoints,
+    EvmAddressToAgent
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -39,10 +47,27 @@ pub struct LinkBaseInput {
     pub content_bytes: Vec<u8>,
 }
 
+// whether an action created an entry of this type. validation checks this before reading a linked
+// entry, since an entry of another type might deserialize as this one or fail with an error
+pub fn has_entry_type(action: &Action, entry_type: UnitEntryTypes) -> ExternResult<bool> {
+    let entry_def = ScopedEntryDefIndex::try_from(entry_type)?;
+    Ok(matches!(
+        action.entry_type(),
+        Some(EntryType::App(def))
+            if def.zome_index == entry_def.zome_index && def.entry_index == entry_def.zome_type
+    ))
+}
+
 #[hdk_extern]
 pub fn genesis_self_check(
     _data: GenesisSelfCheckData,
 ) -> ExternResult<ValidateCallbackResult> {
+    // a board too big to hold or draw would break every move, so don't join a dna with one
+    let dna_properties = dna_properties::DnaProperties::try_from(_data.dna_info.properties)
+        .map_err(|_| wasm_error!("Failed to deserialize properties"))?;
+    if let Err(e) = dna_properties.board_dimensions() {
+        return Ok(ValidateCallbackResult::Invalid(e));
+    }
     Ok(ValidateCallbackResult::Valid)
 }
 pub fn validate_agent_joining(
@@ -73,6 +98,9 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                     EntryTypes::Profile(profile) => {
                         validate_create_profile(EntryCreationAction::Create(action), profile)
                     }
+                    EntryTypes::BoardCheckpoint(board_checkpoint) => {
+                        validate_create_board_checkpoint(EntryCreationAction::Create(action), board_checkpoint)
+                    }
                 },
                 OpEntry::UpdateEntry {
                     app_entry, action, ..
@@ -93,6 +121,9 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                     EntryTypes::Profile(profile) => {
                         validate_create_profile(EntryCreationAction::Update(action), profile)
                     }
+                    EntryTypes::BoardCheckpoint(board_checkpoint) => {
+                        validate_create_board_checkpoint(EntryCreationAction::Update(action), board_checkpoint)
+                    }
                 },
                 _ => Ok(ValidateCallbackResult::Valid),
             },
@@ -132,6 +163,15 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                     (EntryTypes::Profile(profile), EntryTypes::Profile(original_profile)) => {
                         validate_update_profile(action, profile, original_action, original_profile)
                     }
+                    (
+                        EntryTypes::BoardCheckpoint(board_checkpoint),
+                        EntryTypes::BoardCheckpoint(original_board_checkpoint),
+                    ) => validate_update_board_checkpoint(
+                        action,
+                        board_checkpoint,
+                        original_action,
+                        original_board_checkpoint,
+                    ),
                     _ => Ok(ValidateCallbackResult::Invalid(
                         "Original and updated entry types must be the same".to_string(),
                     )),
@@ -160,6 +200,9 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                     EntryTypes::Profile(profile) => {
                         validate_delete_profile(action, original_action, profile)
                     }
+                    EntryTypes::BoardCheckpoint(board_checkpoint) => {
+                        validate_delete_board_checkpoint(action, original_action, board_checkpoint)
+                    }
                 },
                 _ => Ok(ValidateCallbackResult::Valid),
             },
@@ -199,6 +242,15 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                 LinkTypes::AgentToGameMove => {
                     validate_create_link_agent_to_game_move(action, base_address, target_address, tag)
                 }
+                LinkTypes::GameMoveBuckets => {
+                    validate_create_link_game_move_buckets(action, base_address, target_address, tag)
+                }
+                LinkTypes::BoardCheckpoints => {
+                    validate_create_link_board_checkpoints(action, base_address, target_address, tag)
+                }
+                LinkTypes::EvmAddressToAgent => {
+                    validate_create_link_evm_address_to_agent(action, base_address, target_address, tag)
+                }
             },
             OpType::RegisterDeleteLink {
                 link_type,
@@ -248,6 +300,15 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                 LinkTypes::AgentToGameMove => {
                     validate_delete_link_agent_to_game_move(action, original_action, base_address, target_address, tag)
                 }
+                LinkTypes::GameMoveBuckets => {
+                    validate_delete_link_game_move_buckets(action, original_action, base_address, target_address, tag)
+                }
+                LinkTypes::BoardCheckpoints => {
+                    validate_delete_link_board_checkpoints(action, original_action, base_address, target_address, tag)
+                }
+                LinkTypes::EvmAddressToAgent => {
+                    validate_delete_link_evm_address_to_agent(action, original_action, base_address, target_address, tag)
+                }
             },
             OpType::StoreRecord(store_record) => match store_record {
                 OpRecord::CreateEntry { app_entry, action } => match app_entry {
@@ -267,6 +328,9 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                     EntryTypes::Profile(profile) => {
                         validate_create_profile(EntryCreationAction::Create(action), profile)
                     }
+                    EntryTypes::BoardCheckpoint(board_checkpoint) => {
+                        validate_create_board_checkpoint(EntryCreationAction::Create(action), board_checkpoint)
+                    }
                 },
                 OpRecord::UpdateEntry {
                     original_action_hash,
@@ -414,6 +478,38 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                                 Ok(result)
                             }
                         }
+                        EntryTypes::BoardCheckpoint(board_checkpoint) => {
+                            let result = validate_create_board_checkpoint(
+                                EntryCreationAction::Update(action.clone()),
+                                board_checkpoint.clone(),
+                            )?;
+                            if let ValidateCallbackResult::Valid = result {
+                                let original_board_checkpoint: Option<BoardCheckpoint> =
+                                    original_record
+                                        .entry()
+                                        .to_app_option()
+                                        .map_err(|e| wasm_error!(e))?;
+                                let original_board_checkpoint = match original_board_checkpoint {
+                                    Some(board_checkpoint) => board_checkpoint,
+                                    None => {
+                                        return Ok(
+                                                    ValidateCallbackResult::Invalid(
+                                                        "The updated entry type must be the same as the original entry type"
+                                                            .to_string(),
+                                                    ),
+                                                );
+                                    }
+                                };
+                                validate_update_board_checkpoint(
+                                    action,
+                                    board_checkpoint,
+                                    original_action,
+                                    original_board_checkpoint,
+                                )
+                            } else {
+                                Ok(result)
+                            }
+                        }
                     }
                 }
                 OpRecord::DeleteEntry {
@@ -490,6 +586,13 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                         EntryTypes::Profile(original_profile) => {
                             validate_delete_profile(action, original_action, original_profile)
                         }
+                        EntryTypes::BoardCheckpoint(original_board_checkpoint) => {
+                            validate_delete_board_checkpoint(
+                                action,
+                                original_action,
+                                original_board_checkpoint,
+                            )
+                        }
                     }
                 }
                 OpRecord::CreateLink {
@@ -536,6 +639,15 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                     LinkTypes::AgentToGameMove => {
                         validate_create_link_agent_to_game_move(action, base_address, target_address, tag)
                     }
+                    LinkTypes::GameMoveBuckets => {
+                        validate_create_link_game_move_buckets(action, base_address, target_address, tag)
+                    }
+                    LinkTypes::BoardCheckpoints => {
+                        validate_create_link_board_checkpoints(action, base_address, target_address, tag)
+                    }
+                    LinkTypes::EvmAddressToAgent => {
+                        validate_create_link_evm_address_to_agent(action, base_address, target_address, tag)
+                    }
                 },
                 OpRecord::DeleteLink {
                     original_action_hash,
@@ -604,6 +716,15 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                         LinkTypes::AgentToGameMove => {
                             validate_delete_link_agent_to_game_move(action, create_link.clone(), base_address, create_link.target_address, create_link.tag)
                         }
+                        LinkTypes::GameMoveBuckets => {
+                            validate_delete_link_game_move_buckets(action, create_link.clone(), base_address, create_link.target_address, create_link.tag)
+                        }
+                        LinkTypes::BoardCheckpoints => {
+                            validate_delete_link_board_checkpoints(action, create_link.clone(), base_address, create_link.target_address, create_link.tag)
+                        }
+                        LinkTypes::EvmAddressToAgent => {
+                            validate_delete_link_evm_address_to_agent(action, create_link.clone(), base_address, create_link.target_address, create_link.tag)
+                        }
                     }
                 }
                 OpRecord::CreatePrivateEntry { .. } => Ok(ValidateCallbackResult::Valid),
@@ -0,0 +1,199 @@
+// a stand-in for the conductor so validation callbacks can be run in unit tests. it hashes the
+// way the host does and answers the must_get calls from the actions committed through it
+use hdi::prelude::*;
+use std::sync::{Arc, Mutex};
+use crate::*;
+use crate::dna_properties::DnaProperties;
+
+#[derive(Default)]
+struct TestHdiState {
+    properties: SerializedBytes,
+    records: Vec<Record>,
+    activity_read: usize,
+}
+
+#[derive(Clone, Default)]
+pub struct TestHdi(Arc<Mutex<TestHdiState>>);
+
+pub use crate::dna_properties::test_dna_properties as dna_properties;
+
+pub fn agent(byte: u8) -> AgentPubKey {
+    AgentPubKey::from_raw_36(vec![byte; 36])
+}
+
+impl TestHdi {
+    // registers a new host for this test thread, replacing any earlier one
+    pub fn install(dna_properties: DnaProperties) -> Self {
+        let host = TestHdi::default();
+        host.0.lock().unwrap().properties = SerializedBytes::try_from(dna_properties).unwrap();
+        set_hdi(host.clone());
+        host
+    }
+
+    // the hash the next action on this agent's chain will point back to
+    pub fn chain_top(&self, author: &AgentPubKey) -> ActionHash {
+        self.0.lock().unwrap()
+            .records
+            .iter()
+            .rev()
+            .find(|record| record.action().author() == author)
+            .map(|record| record.action_address().clone())
+            // stands in for the genesis actions, which nothing here looks at
+            .unwrap_or_else(|| ActionHash::from_raw_36(author.get_raw_36().to_vec()))
+    }
+
+    fn next_action_seq(&self, author: &AgentPubKey) -> u32 {
+        let state = self.0.lock().unwrap();
+        3 + state.records.iter().filter(|record| record.action().author() == author).count() as u32
+    }
+
+    fn commit(&self, action: Action, entry: Option<Entry>) -> Record {
+        let action = SignedActionHashed::with_presigned(
+            ActionHashed::from_content_sync(action),
+            Signature([0; 64]),
+        );
+        let record = Record::new(action, entry);
+        self.0.lock().unwrap().records.push(record.clone());
+        record
+    }
+
+    pub fn create_entry(&self, author: &AgentPubKey, seconds: i64, entry: EntryTypes) -> Record {
+        let content = Entry::try_from(&entry).unwrap();
+        let action = Action::Create(Create {
+            author: author.clone(),
+            timestamp: Timestamp::from_micros(seconds * 1_000_000),
+            action_seq: self.next_action_seq(author),
+            prev_action: self.chain_top(author),
+            entry_type: EntryType::try_from(&entry).unwrap(),
+            entry_hash: EntryHash::with_data_sync(&content),
+            weight: Default::default(),
+        });
+        self.commit(action, Some(content))
+    }
+
+    pub fn create_link(
+        &self,
+        author: &AgentPubKey,
+        seconds: i64,
+        base_address: AnyLinkableHash,
+        target_address: AnyLinkableHash,
+        link_type: LinkTypes,
+        tag: LinkTag,
+    ) -> CreateLink {
+        let create_link = CreateLink {
+            author: author.clone(),
+            timestamp: Timestamp::from_micros(seconds * 1_000_000),
+            action_seq: self.next_action_seq(author),
+            prev_action: self.chain_top(author),
+            base_address,
+            target_address,
+            zome_index: ZomeIndex(0),
+            link_type: LinkType(link_type as u8),
+            tag,
+            weight: Default::default(),
+        };
+        self.commit(Action::CreateLink(create_link.clone()), None);
+        create_link
+    }
+
+    // how many actions must_get_agent_activity has returned so far
+    pub fn activity_read(&self) -> usize {
+        self.0.lock().unwrap().activity_read
+    }
+
+    fn record(&self, action_hash: &ActionHash) -> Option<Record> {
+        self.0.lock().unwrap().records.iter().find(|record| record.action_address() == action_hash).cloned()
+    }
+}
+
+fn not_found<T>(what: &str) -> ExternResult<T> {
+    Err(wasm_error!(WasmErrorInner::Guest(format!("{} not found", what))))
+}
+
+impl HdiT for TestHdi {
+    fn verify_signature(&self, _: VerifySignature) -> ExternResult<bool> {
+        unimplemented!()
+    }
+    fn hash(&self, hash_input: HashInput) -> ExternResult<HashOutput> {
+        match hash_input {
+            HashInput::Entry(entry) => Ok(HashOutput::Entry(EntryHash::with_data_sync(&entry))),
+            HashInput::Action(action) => Ok(HashOutput::Action(ActionHash::with_data_sync(&action))),
+            _ => unimplemented!(),
+        }
+    }
+    fn must_get_entry(&self, input: MustGetEntryInput) -> ExternResult<EntryHashed> {
+        let state = self.0.lock().unwrap();
+        match state.records.iter().find_map(|record| record.entry().as_option().filter(|entry| EntryHash::with_data_sync(*entry) == input.0)) {
+            Some(entry) => Ok(EntryHashed::from_content_sync(entry.clone())),
+            None => not_found("Entry"),
+        }
+    }
+    fn must_get_action(&self, input: MustGetActionInput) -> ExternResult<SignedActionHashed> {
+        match self.record(&input.0) {
+            Some(record) => Ok(record.signed_action().clone()),
+            None => not_found("Action"),
+        }
+    }
+    fn must_get_valid_record(&self, input: MustGetValidRecordInput) -> ExternResult<Record> {
+        match self.record(&input.0) {
+            Some(record) => Ok(record),
+            None => not_found("Record"),
+        }
+    }
+    // walks back from the chain top like the host does, newest first
+    fn must_get_agent_activity(&self, input: MustGetAgentActivityInput) -> ExternResult<Vec<RegisterAgentActivity>> {
+        let (take, until) = match input.chain_filter.filters {
+            ChainFilters::ToGenesis => (u32::MAX, Default::default()),
+            ChainFilters::Take(take) => (take, Default::default()),
+            ChainFilters::Until(until) => (u32::MAX, until),
+            ChainFilters::Both(take, until) => (take, until),
+        };
+        let mut activity = Vec::new();
+        let mut position = Some(input.chain_filter.chain_top);
+        while let Some(action_hash) = position.take() {
+            if activity.len() as u32 >= take {
+                break;
+            }
+            let Some(record) = self.record(&action_hash) else { break };
+            if record.action().author() != &input.author {
+                break;
+            }
+            if !until.contains(&action_hash) {
+                position = record.action().prev_action().cloned();
+            }
+            activity.push(RegisterAgentActivity { action: record.signed_action().clone(), cached_entry: None });
+        }
+        self.0.lock().unwrap().activity_read += activity.len();
+        Ok(activity)
+    }
+    fn dna_info(&self, _: ()) -> ExternResult<DnaInfo> {
+        Ok(DnaInfo {
+            name: String::from("fractal_tribute"),
+            hash: DnaHash::from_raw_36(vec![9; 36]),
+            properties: self.0.lock().unwrap().properties.clone(),
+            zome_names: vec![ZomeName::from("fractal_tribute_integrity")],
+        })
+    }
+    fn zome_info(&self, _: ()) -> ExternResult<ZomeInfo> {
+        Ok(ZomeInfo {
+            name: ZomeName::from("fractal_tribute_integrity"),
+            id: ZomeIndex(0),
+            properties: SerializedBytes::default(),
+            entry_defs: EntryDefs(Vec::new()),
+            extern_fns: Vec::new(),
+            zome_types: ScopedZomeTypesSet {
+                entries: ScopedZomeTypes(vec![(ZomeIndex(0), (0..UnitEntryTypes::len()).map(EntryDefIndex).collect())]),
+                links: ScopedZomeTypes(vec![(ZomeIndex(0), (0..LinkTypes::len()).map(LinkType).collect())]),
+            },
+        })
+    }
+    fn trace(&self, _: TraceMsg) -> ExternResult<()> {
+        Ok(())
+    }
+    fn x_salsa20_poly1305_decrypt(&self, _: XSalsa20Poly1305Decrypt) -> ExternResult<Option<XSalsa20Poly1305Data>> {
+        unimplemented!()
+    }
+    fn x_25519_x_salsa20_poly1305_decrypt(&self, _: X25519XSalsa20Poly1305Decrypt) -> ExternResult<Option<XSalsa20Poly1305Data>> {
+        unimplemented!()
+    }
+}
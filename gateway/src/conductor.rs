@@ -0,0 +1,247 @@
+// a minimal client for a conductor's app websocket. the request and response types mirror the
+// ones in holochain_conductor_api 0.1, which can't be depended on without building the conductor
+use ed25519_dalek::{Signer, SigningKey};
+use hdk::prelude::*;
+use holochain_websocket::{WebsocketConfig, WebsocketSender};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, Semaphore};
+use url2::Url2;
+
+// how long the conductor should accept a signed call for
+const ZOME_CALL_EXPIRY: Duration = Duration::from_secs(5 * 60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+// how many calls can be made at once, each on its own connection, so a slow render doesn't hold
+// up every other request
+const MAX_CONNECTIONS: usize = 4;
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+enum AppRequest {
+    AppInfo { installed_app_id: String },
+    CallZome(Box<ZomeCall>),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+enum AppResponse {
+    Error(ExternalApiWireError),
+    AppInfo(Option<AppInfo>),
+    ZomeCalled(Box<ExternIO>),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+enum ExternalApiWireError {
+    InternalError(String),
+    Deserialization(String),
+    DnaReadError(String),
+    RibosomeError(String),
+    ActivateApp(String),
+    ZomeCallUnauthorized(String),
+    CountersigningSessionError(String),
+}
+
+impl ExternalApiWireError {
+    fn message(self) -> String {
+        match self {
+            ExternalApiWireError::InternalError(message)
+            | ExternalApiWireError::Deserialization(message)
+            | ExternalApiWireError::DnaReadError(message)
+            | ExternalApiWireError::RibosomeError(message)
+            | ExternalApiWireError::ActivateApp(message)
+            | ExternalApiWireError::ZomeCallUnauthorized(message)
+            | ExternalApiWireError::CountersigningSessionError(message) => message,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct AppInfo {
+    cell_info: HashMap<String, Vec<CellInfo>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum CellInfo {
+    Provisioned(ProvisionedCell),
+    Cloned(serde::de::IgnoredAny),
+    Stem(serde::de::IgnoredAny),
+}
+
+#[derive(Deserialize, Debug)]
+struct ProvisionedCell {
+    cell_id: CellId,
+}
+
+#[derive(Serialize, Debug)]
+struct ZomeCall {
+    cell_id: CellId,
+    zome_name: ZomeName,
+    fn_name: FunctionName,
+    payload: ExternIO,
+    cap_secret: Option<CapSecret>,
+    provenance: AgentPubKey,
+    signature: Signature,
+    nonce: Nonce256Bits,
+    expires_at: Timestamp,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConductorError {
+    NotFound(String),   // the zome couldn't find the token
+    Failed(String),
+}
+
+impl std::fmt::Display for ConductorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConductorError::NotFound(e) | ConductorError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+fn failed(e: impl std::fmt::Display) -> ConductorError {
+    ConductorError::Failed(e.to_string())
+}
+
+// the zome's errors for a token that hasn't been minted, see token_id_to_board
+fn zome_error(error: ExternalApiWireError) -> ConductorError {
+    let message = error.message();
+    if message.contains("No game moves found for that token id") || message.contains("Could not find a game move") {
+        ConductorError::NotFound(message)
+    } else {
+        ConductorError::Failed(message)
+    }
+}
+
+fn now() -> Timestamp {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Timestamp::from_micros(since_epoch.as_micros() as i64)
+}
+
+struct Connection {
+    sender: WebsocketSender,
+    cell_id: CellId,
+}
+
+// calls the fractal_tribute zome of an installed app. the zome grants its read only functions to
+// everyone, so calls are signed with a key made up when the gateway starts rather than an agent's
+pub struct ConductorClient {
+    url: Url2,
+    installed_app_id: String,
+    role_name: String,
+    zome_name: ZomeName,
+    signing_key: SigningKey,
+    cap_secret: Option<CapSecret>,
+    idle_connections: Mutex<Vec<Connection>>,
+    connection_permits: Semaphore,
+}
+
+impl ConductorClient {
+    pub fn new(url: Url2, installed_app_id: String, role_name: String) -> Self {
+        Self {
+            url,
+            installed_app_id,
+            role_name,
+            zome_name: ZomeName::from("fractal_tribute"),
+            signing_key: SigningKey::generate(&mut rand::rngs::OsRng),
+            cap_secret: None,
+            idle_connections: Mutex::new(Vec::new()),
+            connection_permits: Semaphore::new(MAX_CONNECTIONS),
+        }
+    }
+
+    // for calling functions outside the unrestricted grant, once the conductor's admin has granted
+    // them to this client's provenance with this secret
+    pub fn with_cap_secret(mut self, cap_secret: CapSecret) -> Self {
+        self.cap_secret = Some(cap_secret);
+        self
+    }
+
+    // the key calls are signed with
+    pub fn provenance(&self) -> AgentPubKey {
+        AgentPubKey::from_raw_32(self.signing_key.verifying_key().to_bytes().to_vec())
+    }
+
+    async fn connect(&self) -> Result<Connection, ConductorError> {
+        let (mut sender, _) = holochain_websocket::connect(self.url.clone(), Arc::new(WebsocketConfig::default()))
+            .await
+            .map_err(|e| failed(format!("could not connect to {}: {}", self.url, e)))?;
+        let request = AppRequest::AppInfo { installed_app_id: self.installed_app_id.clone() };
+        let app_info = match sender.request_timeout(request, REQUEST_TIMEOUT).await.map_err(failed)? {
+            AppResponse::AppInfo(Some(app_info)) => app_info,
+            AppResponse::AppInfo(None) => return Err(failed(format!("{} is not installed", self.installed_app_id))),
+            AppResponse::Error(e) => return Err(zome_error(e)),
+            response => return Err(failed(format!("unexpected response {:?}", response))),
+        };
+        let cell_id = app_info
+            .cell_info
+            .get(&self.role_name)
+            .and_then(|cells| {
+                cells.iter().find_map(|cell| match cell {
+                    CellInfo::Provisioned(cell) => Some(cell.cell_id.clone()),
+                    _ => None,
+                })
+            })
+            .ok_or(failed(format!("{} has no {} cell", self.installed_app_id, self.role_name)))?;
+        Ok(Connection { sender, cell_id })
+    }
+
+    fn sign_zome_call(&self, cell_id: CellId, fn_name: &str, payload: ExternIO) -> Result<ZomeCall, ConductorError> {
+        let unsigned = ZomeCallUnsigned {
+            provenance: self.provenance(),
+            cell_id,
+            zome_name: self.zome_name.clone(),
+            fn_name: FunctionName::from(fn_name),
+            cap_secret: self.cap_secret,
+            payload,
+            nonce: Nonce256Bits::from(rand::random::<[u8; 32]>()),
+            expires_at: (now() + ZOME_CALL_EXPIRY).map_err(failed)?,
+        };
+        let signature = self.signing_key.sign(&unsigned.data_to_sign().map_err(failed)?);
+        Ok(ZomeCall {
+            cell_id: unsigned.cell_id,
+            zome_name: unsigned.zome_name,
+            fn_name: unsigned.fn_name,
+            payload: unsigned.payload,
+            cap_secret: unsigned.cap_secret,
+            provenance: unsigned.provenance,
+            signature: Signature::from(signature.to_bytes()),
+            nonce: unsigned.nonce,
+            expires_at: unsigned.expires_at,
+        })
+    }
+
+    // calls a zome function on an idle connection, connecting first if there isn't one. the pool
+    // is only locked to take and return a connection, not for the call. a connection that fails
+    // is dropped so a later call makes a new one
+    pub async fn call_zome<I, O>(&self, fn_name: &str, input: I) -> Result<O, ConductorError>
+    where
+        I: Serialize + std::fmt::Debug,
+        O: serde::de::DeserializeOwned + std::fmt::Debug,
+    {
+        let _permit = self.connection_permits.acquire().await.map_err(failed)?;
+        let idle = self.idle_connections.lock().await.pop();
+        let mut connection = match idle {
+            Some(connection) => connection,
+            None => self.connect().await?,
+        };
+
+        let payload = ExternIO::encode(input).map_err(failed)?;
+        let zome_call = self.sign_zome_call(connection.cell_id.clone(), fn_name, payload)?;
+        let response = connection
+            .sender
+            .request_timeout(AppRequest::CallZome(Box::new(zome_call)), REQUEST_TIMEOUT)
+            .await
+            .map_err(failed)?;
+        // the connection is fine whatever the zome answered
+        self.idle_connections.lock().await.push(connection);
+        match response {
+            AppResponse::ZomeCalled(output) => output.decode().map_err(failed),
+            AppResponse::Error(e) => Err(zome_error(e)),
+            response => Err(failed(format!("unexpected response {:?}", response))),
+        }
+    }
+}
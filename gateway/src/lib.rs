@@ -0,0 +1,27 @@
+pub mod conductor;
+pub mod routes;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::Server;
+use routes::*;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+// serves token metadata and images until the server fails, see routes::handle
+pub async fn serve<S: TokenSource>(address: SocketAddr, source: S, config: GatewayConfig) -> Result<(), hyper::Error> {
+    let source = Arc::new(source);
+    let config = Arc::new(config);
+    let make_service = make_service_fn(move |_| {
+        let source = source.clone();
+        let config = config.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let source = source.clone();
+                let config = config.clone();
+                async move { Ok::<_, Infallible>(handle(&*source, &config, request).await) }
+            }))
+        }
+    });
+    Server::bind(&address).serve(make_service).await
+}
@@ -0,0 +1,56 @@
+use gateway::conductor::ConductorClient;
+use gateway::routes::GatewayConfig;
+use std::collections::HashMap;
+
+const USAGE: &str = "usage:
+  gateway --conductor ws://localhost:PORT [--app-id fractal_tribute] [--role fractal_tribute]
+      [--listen 127.0.0.1:8080] [--size Small|Large|PIXELS] [--max-age SECONDS] [--public-url URL]
+      serve /metadata/{id}.json and /image/{id}.png|webp|svg from a conductor's app interface";
+
+// --name value options
+fn parse_args(args: &[String]) -> Result<HashMap<String, String>, String> {
+    let mut options = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let name = arg.strip_prefix("--").ok_or(String::from(USAGE))?;
+        let value = args.next().ok_or(format!("missing value for --{}\n{}", name, USAGE))?;
+        options.insert(name.to_string(), value.clone());
+    }
+    Ok(options)
+}
+
+fn option_or<T: std::str::FromStr>(options: &HashMap<String, String>, name: &str, default: T) -> Result<T, String> {
+    match options.get(name) {
+        Some(value) => value.parse().map_err(|_| format!("invalid value for --{}: {}", name, value)),
+        None => Ok(default),
+    }
+}
+
+async fn run() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = parse_args(&args)?;
+    let conductor = options.get("conductor").ok_or(String::from(USAGE))?;
+    let conductor = url2::Url2::try_parse(conductor).map_err(|e| format!("invalid --conductor url: {}", e))?;
+    let listen: std::net::SocketAddr = option_or(&options, "listen", "127.0.0.1:8080".parse().unwrap())?;
+
+    let client = ConductorClient::new(
+        conductor,
+        option_or(&options, "app-id", String::from("fractal_tribute"))?,
+        option_or(&options, "role", String::from("fractal_tribute"))?,
+    );
+    let config = GatewayConfig {
+        board_size: option_or(&options, "size", String::from("Large"))?,
+        max_age_seconds: option_or(&options, "max-age", 3600)?,
+        public_url: options.get("public-url").cloned(),
+    };
+    println!("serving token metadata and images on http://{}", listen);
+    gateway::serve(listen, client, config).await.map_err(|e| e.to_string())
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
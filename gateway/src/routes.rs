@@ -0,0 +1,157 @@
+use ethers_core::types::U256;
+use ethers_core::utils::{hex, keccak256};
+use fractal_tribute::board::TokenIdToImageInput;
+use fractal_tribute::board_images::BoardImage;
+use hyper::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use std::future::Future;
+use crate::conductor::{ConductorClient, ConductorError};
+
+// where the gateway gets token metadata and images from, a conductor outside of tests
+pub trait TokenSource: Send + Sync + 'static {
+    fn token_metadata(&self, token_id: String) -> impl Future<Output = Result<String, ConductorError>> + Send;
+    fn token_image(&self, input: TokenIdToImageInput) -> impl Future<Output = Result<BoardImage, ConductorError>> + Send;
+}
+
+impl TokenSource for ConductorClient {
+    fn token_metadata(&self, token_id: String) -> impl Future<Output = Result<String, ConductorError>> + Send {
+        self.call_zome("token_id_to_metadata", token_id)
+    }
+
+    fn token_image(&self, input: TokenIdToImageInput) -> impl Future<Output = Result<BoardImage, ConductorError>> + Send {
+        self.call_zome("token_id_to_image", input)
+    }
+}
+
+pub struct GatewayConfig {
+    pub board_size: String,           // Small, Large or a number of pixels, for png and webp images
+    pub max_age_seconds: u32,         // how long clients and caches may keep a response
+    pub public_url: Option<String>,   // when set, metadata points at this gateway's png instead of embedding an svg
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Route {
+    Metadata { token_id: String },
+    Image { token_id: String, format: &'static str },
+}
+
+// token ids are decimal, or 64 lowercase hex digits where a client has substituted ERC-1155's {id}.
+// a 64 digit decimal id would read as either, so only ids with a hex letter are taken as hex
+pub fn parse_token_id(token_id: &str) -> Option<String> {
+    let is_hex = token_id.len() == 64
+        && token_id.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        && token_id.bytes().any(|b| (b'a'..=b'f').contains(&b));
+    let parsed = if is_hex {
+        U256::from_str_radix(token_id, 16).ok()?
+    } else if !token_id.is_empty() && token_id.bytes().all(|b| b.is_ascii_digit()) {
+        U256::from_dec_str(token_id).ok()?
+    } else {
+        return None;
+    };
+    Some(parsed.to_string())
+}
+
+pub fn parse_route(path: &str) -> Option<Route> {
+    let (directory, file) = path.strip_prefix('/')?.split_once('/')?;
+    let (token_id, extension) = file.rsplit_once('.')?;
+    let token_id = parse_token_id(token_id)?;
+    match (directory, extension) {
+        ("metadata", "json") => Some(Route::Metadata { token_id }),
+        ("image", "png") => Some(Route::Image { token_id, format: "Png" }),
+        ("image", "webp") => Some(Route::Image { token_id, format: "Webp" }),
+        ("image", "svg") => Some(Route::Image { token_id, format: "Svg" }),
+        _ => None,
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    let mut response = Response::new(Body::from(format!("{}\n", message)));
+    *response.status_mut() = status;
+    response.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    response
+}
+
+fn conductor_error_response(error: ConductorError) -> Response<Body> {
+    match error {
+        ConductorError::NotFound(_) => error_response(StatusCode::NOT_FOUND, "token not found"),
+        ConductorError::Failed(e) => error_response(StatusCode::BAD_GATEWAY, &e),
+    }
+}
+
+// a hash of the content that stays the same across builds and gateway instances, so caches behind
+// a load balancer agree on it
+fn etag(bytes: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(keccak256(bytes)))
+}
+
+// a cacheable response, or 304 Not Modified if the client already has these bytes
+fn cached_response(request: &Request<Body>, config: &GatewayConfig, bytes: Vec<u8>, content_type: &str) -> Response<Body> {
+    let etag = etag(&bytes);
+    let not_modified = request
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+
+    let mut response = if not_modified {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        response
+    } else {
+        let mut response = Response::new(Body::from(bytes));
+        if let Ok(content_type) = HeaderValue::from_str(content_type) {
+            response.headers_mut().insert(CONTENT_TYPE, content_type);
+        }
+        response
+    };
+    let headers = response.headers_mut();
+    headers.insert(ETAG, HeaderValue::from_str(&etag).unwrap());
+    headers.insert(CACHE_CONTROL, HeaderValue::from_str(&format!("public, max-age={}", config.max_age_seconds)).unwrap());
+    response
+}
+
+// points the metadata's image at the gateway, marketplaces handle a url better than a large data uri
+fn with_public_image(metadata: String, config: &GatewayConfig, token_id: &str) -> Result<String, ConductorError> {
+    let public_url = match &config.public_url {
+        Some(public_url) => public_url.trim_end_matches('/'),
+        None => return Ok(metadata),
+    };
+    let mut json: serde_json::Value = serde_json::from_str(&metadata).map_err(|e| ConductorError::Failed(e.to_string()))?;
+    json["image"] = serde_json::Value::String(format!("{}/image/{}.png", public_url, token_id));
+    Ok(json.to_string())
+}
+
+pub async fn handle<S: TokenSource>(source: &S, config: &GatewayConfig, request: Request<Body>) -> Response<Body> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return error_response(StatusCode::METHOD_NOT_ALLOWED, "only GET and HEAD are supported");
+    }
+    let route = match parse_route(request.uri().path()) {
+        Some(route) => route,
+        None => return error_response(StatusCode::NOT_FOUND, "expected /metadata/{id}.json or /image/{id}.png|webp|svg"),
+    };
+
+    let mut response = match route {
+        Route::Metadata { token_id } => {
+            match source.token_metadata(token_id.clone()).await.and_then(|metadata| with_public_image(metadata, config, &token_id)) {
+                Ok(metadata) => cached_response(&request, config, metadata.into_bytes(), "application/json"),
+                Err(e) => conductor_error_response(e),
+            }
+        }
+        Route::Image { token_id, format } => {
+            let input = TokenIdToImageInput {
+                token_id,
+                board_size: config.board_size.clone(),
+                format: format.to_string(),
+            };
+            match source.token_image(input).await {
+                Ok(image) => cached_response(&request, config, image.bytes, &image.content_type),
+                Err(e) => conductor_error_response(e),
+            }
+        }
+    };
+    if request.method() == Method::HEAD {
+        *response.body_mut() = Body::empty();
+    }
+    response.headers_mut().insert("access-control-allow-origin", HeaderValue::from_static("*"));
+    response
+}
@@ -0,0 +1,117 @@
+// the gateway's http behaviour, against a fake conductor
+use ethers_core::utils::{hex, keccak256};
+use fractal_tribute::board::TokenIdToImageInput;
+use fractal_tribute::board_images::BoardImage;
+use gateway::conductor::ConductorError;
+use gateway::routes::*;
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+const MINTED_TOKEN_ID: &str = "42";
+
+struct FakeConductor;
+
+impl TokenSource for FakeConductor {
+    async fn token_metadata(&self, token_id: String) -> Result<String, ConductorError> {
+        match token_id.as_str() {
+            MINTED_TOKEN_ID => Ok(String::from(r#"{"name":"Fractal Tribute #7","image":"data:image/svg+xml;base64,"}"#)),
+            _ => Err(ConductorError::NotFound(String::from("No game moves found for that token id"))),
+        }
+    }
+
+    async fn token_image(&self, input: TokenIdToImageInput) -> Result<BoardImage, ConductorError> {
+        if input.token_id != MINTED_TOKEN_ID {
+            return Err(ConductorError::NotFound(String::from("No game moves found for that token id")));
+        }
+        let content_type = match input.format.as_str() {
+            "Png" => "image/png",
+            "Webp" => "image/webp",
+            "Svg" => "image/svg+xml",
+            _ => return Err(ConductorError::Failed(String::from("Image format must be Png, Webp or Svg"))),
+        };
+        Ok(BoardImage { bytes: format!("{} {}", input.format, input.board_size).into_bytes(), content_type: content_type.to_string() })
+    }
+}
+
+fn config(public_url: Option<&str>) -> GatewayConfig {
+    GatewayConfig { board_size: String::from("Large"), max_age_seconds: 600, public_url: public_url.map(String::from) }
+}
+
+async fn get(path: &str, config: &GatewayConfig, if_none_match: Option<&str>) -> Response<Body> {
+    let mut request = Request::builder().method(Method::GET).uri(path);
+    if let Some(etag) = if_none_match {
+        request = request.header("if-none-match", etag);
+    }
+    handle(&FakeConductor, config, request.body(Body::empty()).unwrap()).await
+}
+
+async fn body(response: Response<Body>) -> String {
+    String::from_utf8(hyper::body::to_bytes(response.into_body()).await.unwrap().to_vec()).unwrap()
+}
+
+fn header<'a>(response: &'a Response<Body>, name: &str) -> &'a str {
+    response.headers().get(name).unwrap().to_str().unwrap()
+}
+
+#[test]
+fn parses_decimal_and_erc1155_hex_token_ids() {
+    assert_eq!(parse_token_id("42"), Some(String::from("42")));
+    assert_eq!(parse_token_id(&format!("{:064x}", 42)), Some(String::from("42")));
+    let decimal = "1".repeat(64);
+    assert_eq!(parse_token_id(&decimal), Some(decimal.clone()));
+    assert_eq!(parse_token_id(&format!("{:064X}", 42)), None);
+    assert_eq!(parse_token_id("0x2a"), None);
+    assert_eq!(parse_token_id(""), None);
+    assert_eq!(parse_route("/image/42.svg"), Some(Route::Image { token_id: String::from("42"), format: "Svg" }));
+    assert_eq!(parse_route("/image/42.gif"), None);
+    assert_eq!(parse_route("/metadata/42"), None);
+}
+
+#[tokio::test]
+async fn serves_metadata_with_caching_headers() {
+    let config = config(None);
+    let response = get("/metadata/42.json", &config, None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(header(&response, "content-type"), "application/json");
+    assert_eq!(header(&response, "cache-control"), "public, max-age=600");
+    let etag = header(&response, "etag").to_string();
+    let metadata = body(response).await;
+    assert!(metadata.contains("Fractal Tribute #7"));
+    assert_eq!(etag, format!("\"{}\"", hex::encode(keccak256(metadata.as_bytes()))));
+
+    let response = get("/metadata/42.json", &config, Some(&etag)).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(header(&response, "etag"), etag);
+    assert_eq!(body(response).await, "");
+}
+
+#[tokio::test]
+async fn points_metadata_at_the_public_png() {
+    let response = get(&format!("/metadata/{:064x}.json", 42), &config(Some("https://tokens.example.com/")), None).await;
+    let json: serde_json::Value = serde_json::from_str(&body(response).await).unwrap();
+    assert_eq!(json["image"], "https://tokens.example.com/image/42.png");
+    assert_eq!(json["name"], "Fractal Tribute #7");
+}
+
+#[tokio::test]
+async fn serves_images_in_each_format() {
+    let config = config(None);
+    for (extension, content_type, format) in [("png", "image/png", "Png"), ("webp", "image/webp", "Webp"), ("svg", "image/svg+xml", "Svg")] {
+        let response = get(&format!("/image/42.{}", extension), &config, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, "content-type"), content_type);
+        assert_eq!(body(response).await, format!("{} Large", format));
+    }
+}
+
+#[tokio::test]
+async fn unknown_tokens_and_paths_are_not_found_and_not_cached() {
+    let config = config(None);
+    for path in ["/metadata/43.json", "/image/43.png", "/metadata/forty-two.json", "/"] {
+        let response = get(path, &config, None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+        assert_eq!(header(&response, "cache-control"), "no-store");
+    }
+
+    let request = Request::builder().method(Method::POST).uri("/metadata/42.json").body(Body::empty()).unwrap();
+    assert_eq!(handle(&FakeConductor, &config, request).await.status(), StatusCode::METHOD_NOT_ALLOWED);
+}
@@ -0,0 +1,223 @@
+// runs the gateway against a real conductor. needs hc on the path and the dna packed first:
+//   npm run build:happ && cargo test -p gateway --test sandbox -- --ignored
+use ethers_core::rand::thread_rng;
+use ethers_core::types::U256;
+use ethers_core::utils::keccak256;
+use ethers_signers::{LocalWallet, Signer};
+use fractal_tribute::render_cache::RenderCacheStats;
+use fractal_tribute_integrity::*;
+use gateway::conductor::{ConductorClient, ConductorError};
+use gateway::routes::GatewayConfig;
+use hdk::prelude::*;
+use holochain_websocket::{WebsocketConfig, WebsocketSender};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Response, StatusCode};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+
+const DNA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../dnas/fractal_tribute/workdir/fractal_tribute.dna");
+
+// the workdir dna's game has ended, so the sandbox gets a happ whose game is open until 2100
+const HAPP_MANIFEST: &str = r#"---
+manifest_version: "1"
+name: fractal_tribute
+description: ~
+roles:
+  - name: fractal_tribute
+    provisioning:
+      strategy: create
+      deferred: false
+    dna:
+      bundled: "{dna}"
+      modifiers:
+        network_seed: ~
+        properties:
+          nft_contract_address: 0xcC75696E62E1654940d2024B783d9B263ef201f9
+          payment_token_address: 0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270
+          game_start_time: 0
+          game_end_time: 4102444800
+          game_master_evm_key: 0x74423442CEA6B5c90d13C2d7C21B0FcE723ECe6d
+          chain_id: 137
+        origin_time: ~
+        quantum_time: ~
+      version: ~
+      clone_limit: 0
+"#;
+
+// the admin requests the test needs, mirroring holochain_conductor_api 0.1 like gateway::conductor
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+enum AdminRequest {
+    ListCellIds,
+    GrantZomeCallCapability(Box<GrantZomeCallCapabilityPayload>),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+enum AdminResponse {
+    Error(serde_json::Value),
+    CellIdsListed(Vec<CellId>),
+    ZomeCallCapabilityGranted,
+}
+
+// the sandbox conductor, killed when the test ends however it ends
+struct Sandbox(Child);
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = Command::new("hc").args(["sandbox", "clean"]).status();
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+// packs HAPP_MANIFEST next to the test's other temporary files
+fn pack_happ() -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("fractal_tribute_sandbox_{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("happ.yaml"), HAPP_MANIFEST.replace("{dna}", DNA)).unwrap();
+    let status = Command::new("hc").args(["app", "pack"]).arg(&directory).status().expect("Could not run hc app pack");
+    assert!(status.success(), "Could not pack the happ, has the dna been built?");
+    directory.join("fractal_tribute.happ")
+}
+
+fn start_sandbox(happ: &PathBuf, admin_port: u16, app_port: u16) -> Sandbox {
+    let mut child = Command::new("hc")
+        .arg("sandbox")
+        .arg(format!("--force-admin-ports={}", admin_port))
+        .args(["--piped", "generate"])
+        .arg(happ)
+        .args(["--app-id", "fractal_tribute"])
+        .arg(format!("--run={}", app_port))
+        .args(["network", "mem"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Could not run hc sandbox, is it installed?");
+    use std::io::Write;
+    child.stdin.take().unwrap().write_all(b"pass\n").unwrap();
+    Sandbox(child)
+}
+
+// the conductor takes a while to install the app, retry until the zome answers
+async fn wait_for_zome(client: &ConductorClient) {
+    for _ in 0..120 {
+        if client.call_zome::<(), RenderCacheStats>("get_render_cache_stats", ()).await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    panic!("The sandbox conductor did not start");
+}
+
+async fn admin_request(sender: &mut WebsocketSender, request: AdminRequest) -> AdminResponse {
+    match sender.request_timeout(request, Duration::from_secs(60)).await.unwrap() {
+        AdminResponse::Error(e) => panic!("The admin request failed: {}", e),
+        response => response,
+    }
+}
+
+// lets writer make the calls a player's ui would, which the unrestricted grant doesn't cover
+async fn grant_writer(admin_port: u16, writer: &ConductorClient, cap_secret: CapSecret) -> AgentPubKey {
+    let url = url2::Url2::parse(format!("ws://127.0.0.1:{}", admin_port));
+    let (mut sender, _) = holochain_websocket::connect(url, Arc::new(WebsocketConfig::default())).await.unwrap();
+    let cell_id = match admin_request(&mut sender, AdminRequest::ListCellIds).await {
+        AdminResponse::CellIdsListed(cell_ids) => cell_ids.into_iter().next().expect("The sandbox has no cells"),
+        response => panic!("unexpected response {:?}", response),
+    };
+    let cap_grant = ZomeCallCapGrant {
+        tag: String::from("sandbox test"),
+        access: CapAccess::Assigned { secret: cap_secret, assignees: BTreeSet::from([writer.provenance()]) },
+        functions: GrantedFunctions::All,
+    };
+    let payload = GrantZomeCallCapabilityPayload { cell_id: cell_id.clone(), cap_grant };
+    admin_request(&mut sender, AdminRequest::GrantZomeCallCapability(Box::new(payload))).await;
+    cell_id.agent_pubkey().clone()
+}
+
+// binds a new evm key to the agent, makes a move and mints its token id, see create_link_base
+async fn mint_token(writer: &ConductorClient, agent: &AgentPubKey) -> String {
+    let wallet = LocalWallet::new(&mut thread_rng());
+    let signature = wallet.sign_message(agent.get_raw_39()).await.unwrap();
+    let evm_key_binding = EvmKeyBinding {
+        evm_key: wallet.address().as_bytes().to_vec(),
+        signature_bytes: signature.to_vec(),
+        signature_scheme: EvmSignatureScheme::PersonalSign,
+        handover: None,
+        attestation: None,
+    };
+    let _binding: Record = writer.call_zome("create_evm_key_binding", evm_key_binding.clone()).await.unwrap();
+
+    let game_move = GameMove {
+        changes: vec![PixelChange { x: 3, y: 4, color: Color { r: 200, g: 10, b: 40 }, graphic_option: 2 }],
+    };
+    let record: Record = writer.call_zome("create_game_move", game_move.to_bytes()).await.unwrap();
+    let game_move_hash = record.action_address().clone();
+    let _: () = writer.call_zome("create_tokenid_for_game_move", game_move_hash.clone()).await.unwrap();
+
+    let mut hash_input = vec![0u8; 12];
+    hash_input.extend_from_slice(&evm_key_binding.evm_key);
+    hash_input.extend_from_slice(&keccak256(game_move_hash.get_raw_39()));
+    U256::from_big_endian(&keccak256(hash_input)).to_string()
+}
+
+// the token's links take a moment to be integrated, so retry until the gateway finds it
+async fn get_when_found(http: &Client<hyper::client::HttpConnector>, gateway_port: u16, path: &str) -> Response<Body> {
+    for _ in 0..60 {
+        let uri = format!("http://127.0.0.1:{}{}", gateway_port, path).parse().unwrap();
+        let response = http.get(uri).await.unwrap();
+        if response.status() != StatusCode::NOT_FOUND {
+            return response;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    panic!("The gateway never found {}", path);
+}
+
+#[tokio::test]
+#[ignore]
+async fn serves_tokens_from_a_sandbox_conductor() {
+    let (admin_port, app_port) = (free_port(), free_port());
+    let _sandbox = start_sandbox(&pack_happ(), admin_port, app_port);
+    let url = url2::Url2::parse(format!("ws://127.0.0.1:{}", app_port));
+
+    let client = ConductorClient::new(url.clone(), String::from("fractal_tribute"), String::from("fractal_tribute"));
+    wait_for_zome(&client).await;
+    let missing: Result<String, ConductorError> = client.call_zome("token_id_to_metadata", String::from("1")).await;
+    assert!(matches!(missing, Err(ConductorError::NotFound(_))), "{:?}", missing);
+
+    let gateway_port = free_port();
+    let config = GatewayConfig { board_size: String::from("Small"), max_age_seconds: 60, public_url: None };
+    let source = ConductorClient::new(url.clone(), String::from("fractal_tribute"), String::from("fractal_tribute"));
+    tokio::spawn(gateway::serve(([127, 0, 0, 1], gateway_port).into(), source, config));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // no moves have been minted in a fresh sandbox, so every token is missing
+    let http = Client::new();
+    for path in ["/metadata/1.json", "/image/1.png", "/image/1.svg"] {
+        let uri = format!("http://127.0.0.1:{}{}", gateway_port, path).parse().unwrap();
+        let response = http.get(uri).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+
+    // then a player mints one, which the gateway serves
+    let cap_secret = CapSecret::from([7; 64]);
+    let writer = ConductorClient::new(url, String::from("fractal_tribute"), String::from("fractal_tribute")).with_cap_secret(cap_secret);
+    let agent = grant_writer(admin_port, &writer, cap_secret).await;
+    let token_id = mint_token(&writer, &agent).await;
+
+    for (path, content_type) in [
+        (format!("/metadata/{}.json", token_id), "application/json"),
+        (format!("/image/{}.png", token_id), "image/png"),
+    ] {
+        let response = get_when_found(&http, gateway_port, &path).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        assert_eq!(response.headers()[CONTENT_TYPE], content_type, "{}", path);
+    }
+}
@@ -5,6 +5,7 @@ export async function transformDnaProperties(original: DnaProperties): Promise<T
     return {
         nftContractAddress: getAddress(original.nft_contract_address),
         paymentTokenAddress: getAddress(original.payment_token_address),
+        gameStartTime: new Date(parseInt(original.game_start_time ?? "0") * 1000),
         gameEndTime: new Date(parseInt(original.game_end_time) * 1000),
         gameMasterEvmKey: getAddress(original.game_master_evm_key),
         chainId: parseInt(original.chain_id)
@@ -88,10 +88,62 @@ export class DnaInterface {
     }
 
     // evm key binding
-    async createEvmKeyBinding(evmKeyBindingEntry: EvmKeyBinding): Promise<Record> {
+    // the eth_signTypedData_v4 request this agent's wallet signs to bind its key
+    async getEvmKeyBindingTypedData(): Promise<any> {
+        const typedData = await this.client.callZome({
+            cap_secret: null,
+            role_name,
+            zome_name,
+            fn_name: 'get_evm_key_binding_typed_data',
+            payload: null,
+        }) as string
+        return JSON.parse(typedData)
+    }
+
+    private evmKeyBindingPayload(evmKeyBindingEntry: EvmKeyBinding): any {
         let _evmKeyBinding: any = {}
         _evmKeyBinding.evm_key = Array.from(evmKeyBindingEntry.evm_key)
         _evmKeyBinding.signature_bytes = Array.from(evmKeyBindingEntry.signature_bytes)
+        _evmKeyBinding.signature_scheme = evmKeyBindingEntry.signature_scheme ?? 'PersonalSign'
+        if (evmKeyBindingEntry.handover) {
+            _evmKeyBinding.handover = {
+                signer: evmKeyBindingEntry.handover.signer,
+                signature_bytes: Array.from(evmKeyBindingEntry.handover.signature_bytes),
+            }
+        }
+        if (evmKeyBindingEntry.attestation) {
+            _evmKeyBinding.attestation = Array.from(evmKeyBindingEntry.attestation)
+        }
+        return _evmKeyBinding
+    }
+
+    // the eth_signTypedData_v4 request the current key, or the game master, signs to hand over to newAddress
+    async getEvmKeyRotationTypedData(newAddress: Address): Promise<any> {
+        const typedData = await this.client.callZome({
+            cap_secret: null,
+            role_name,
+            zome_name,
+            fn_name: 'get_evm_key_rotation_typed_data',
+            payload: Array.from(hexToBytes(newAddress)),
+        }) as string
+        return JSON.parse(typedData)
+    }
+
+    async rotateEvmKeyBinding(evmKeyBindingEntry: EvmKeyBinding): Promise<Record> {
+        const record = await this.client.callZome({
+            cap_secret: null,
+            role_name,
+            zome_name,
+            fn_name: 'rotate_evm_key_binding',
+            payload: this.evmKeyBindingPayload(evmKeyBindingEntry),
+        }) as Record
+        this.agentEvmKeys.delete(this.myPubKey().toString())
+        setIsHotHolder(bytesToHex(evmKeyBindingEntry.evm_key));
+        return record
+    }
+
+    async createEvmKeyBinding(evmKeyBindingEntry: EvmKeyBinding): Promise<Record> {
+        const _evmKeyBinding = this.evmKeyBindingPayload(evmKeyBindingEntry)
         try {
             const record = await this.client.callZome({
                 cap_secret: null,
@@ -155,6 +207,21 @@ export class DnaInterface {
         }
     }
 
+    // the agent an evm address is bound to, or null if nobody has bound it or they have rotated away from it
+    async getAgentForEvmAddress(address: Address): Promise<AgentPubKey | null> {
+        try {
+            return await this.client.callZome({
+                cap_secret: null,
+                role_name,
+                zome_name,
+                fn_name: 'get_agent_for_evm_address',
+                payload: Array.from(hexToBytes(address)),
+            }) as AgentPubKey | null
+        } catch (e) {
+            console.log(e?.data?.data || e)
+        }
+    }
+
     // profile
     async createProfile(profile: Profile): Promise<Record> {
         try {
@@ -4,23 +4,36 @@ import type { Address, Hex } from 'viem';
 export type DnaProperties = {
   nft_contract_address: string;
   payment_token_address: string;
+  game_start_time?: string;
   game_end_time: string;
   game_master_evm_key: string;
   chain_id: string;
+  contract_wallet_verifier_evm_key?: string;
 };
 
 export type TransformedDnaProperties = {
   nftContractAddress: Address;
   paymentTokenAddress: Address;
+  gameStartTime: Date;
   gameEndTime: Date;
   gameMasterEvmKey: Address;
   chainId: number;
 };
 
 // EVM key binding
+export type EvmSignatureScheme = 'PersonalSign' | 'Eip712' | 'ContractWallet';
+
+export type EvmKeyHandover = {
+  signer: 'PreviousKey' | 'GameMaster';
+  signature_bytes: Uint8Array;
+}
+
 export type EvmKeyBinding = {
   evm_key: Uint8Array;
   signature_bytes: Uint8Array;
+  signature_scheme?: EvmSignatureScheme;  // PersonalSign when missing
+  handover?: EvmKeyHandover;              // only when rotating to a new key
+  attestation?: Uint8Array;               // the contract wallet verifier's signature, for ContractWallet bindings
 }
 
 // Profile
@@ -73,6 +86,14 @@ export type IncomingBoardWithMetadata = {
   bytes: Uint8Array,
   creator: AgentPubKey
   creation_hash: ActionHash
+  diagnostics: GameMoveLinkDiagnostic[]
+}
+
+export type GameMoveLinkDiagnostic = {
+  create_link_hash: ActionHash,
+  target: Uint8Array,
+  author: AgentPubKey,
+  error: string,
 }
 
 export type IncomingBoardWithMetadataAndId = {